@@ -0,0 +1,174 @@
+//! Derive macros for `rs8583`. Use through the `derive` feature of the main
+//! crate; see `rs8583::de` for details.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, GenericArgument, LitInt, PathArguments, Type,
+};
+
+/// Generate `from_message` and `to_message` for a struct whose fields are
+/// annotated with `#[de(N)]`, `N` being the ISO 8583 data element number.
+#[proc_macro_derive(DataElements, attributes(de))]
+pub fn derive_data_elements(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "DataElements requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "DataElements can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut getters = Vec::new();
+    let mut setters = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let de = data_element(field)?;
+        let idx = de - 1;
+        if is_option(&field.ty) {
+            getters.push(quote! {
+                #ident: ::rs8583::de::get_optional_value(msg, #idx)?
+            });
+            setters.push(quote! {
+                ::rs8583::de::set_optional_value(msg, #idx, &self.#ident)?;
+            });
+        } else {
+            getters.push(quote! {
+                #ident: ::rs8583::de::get_value(msg, #idx)?
+            });
+            setters.push(quote! {
+                ::rs8583::de::set_value(msg, #idx, &self.#ident)?;
+            });
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            pub fn from_message(
+                msg: &::rs8583::Message<'_>,
+            ) -> ::std::result::Result<Self, ::rs8583::error::RS8583Error> {
+                Ok(Self {
+                    #(#getters,)*
+                })
+            }
+
+            pub fn to_message(
+                &self,
+                msg: &mut ::rs8583::Message<'_>,
+            ) -> ::std::result::Result<(), ::rs8583::error::RS8583Error> {
+                #(#setters)*
+                Ok(())
+            }
+        }
+    })
+}
+
+fn data_element(field: &syn::Field) -> syn::Result<usize> {
+    for attr in &field.attrs {
+        if attr.path().is_ident("de") {
+            let lit: LitInt = attr.parse_args()?;
+            let de: usize = lit.base10_parse()?;
+            if de < 2 {
+                return Err(syn::Error::new_spanned(
+                    lit,
+                    "data element number must be 2 or above",
+                ));
+            }
+            if de > 192 {
+                return Err(syn::Error::new_spanned(
+                    lit,
+                    "data element number must be 192 or below",
+                ));
+            }
+            if de % 64 == 1 {
+                return Err(syn::Error::new_spanned(
+                    lit,
+                    format!("data element {} is a bitmap continuation bit", de),
+                ));
+            }
+            return Ok(de);
+        }
+    }
+    Err(syn::Error::new_spanned(field, "missing #[de(N)] attribute"))
+}
+
+fn is_option(ty: &Type) -> bool {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    return matches!(args.args.first(), Some(GenericArgument::Type(_)));
+                }
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proc_macro2::Span;
+    use syn::parse_quote;
+
+    fn de_error(de: usize) -> Option<String> {
+        let lit = LitInt::new(&de.to_string(), Span::call_site());
+        let input: DeriveInput = parse_quote!(
+            struct S {
+                #[de(#lit)]
+                a: String,
+            }
+        );
+        match &input.data {
+            Data::Struct(data) => data_element(data.fields.iter().next().unwrap())
+                .err()
+                .map(|e| e.to_string()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn data_element_range() {
+        assert_eq!(de_error(2), None);
+        assert_eq!(de_error(128), None);
+        assert_eq!(de_error(192), None);
+        assert_eq!(
+            de_error(1),
+            Some(String::from("data element number must be 2 or above"))
+        );
+        assert_eq!(
+            de_error(65),
+            Some(String::from("data element 65 is a bitmap continuation bit"))
+        );
+        assert_eq!(
+            de_error(129),
+            Some(String::from(
+                "data element 129 is a bitmap continuation bit"
+            ))
+        );
+        assert_eq!(
+            de_error(193),
+            Some(String::from("data element number must be 192 or below"))
+        );
+    }
+}
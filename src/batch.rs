@@ -0,0 +1,177 @@
+//! Reading files or streams holding many consecutive messages.
+
+use bytes::Bytes;
+
+use crate::codec::{Codec, Framing};
+use crate::error::RS8583Error;
+use crate::msg::Message;
+use crate::spec::MessageSpec;
+
+/// Iterator over the messages of a batch, yielding one result per message.
+///
+/// With framed input, a message that fails to parse is reported and reading
+/// resumes at the next frame. Unframed input has no message boundaries to
+/// resynchronize on, so the first error ends the iteration; so does a
+/// truncated frame. Zero-length frames (keep-alives) are skipped.
+pub struct BatchReader<'spec, 'c> {
+    spec: &'spec MessageSpec,
+    codec: &'c Codec,
+    cursor: Bytes,
+    failed: bool,
+}
+
+impl<'spec, 'c> BatchReader<'spec, 'c> {
+    /// Fails if the codec settings contradict each other (see
+    /// [`Codec::validate`]).
+    pub fn new(
+        spec: &'spec MessageSpec,
+        codec: &'c Codec,
+        data: Bytes,
+    ) -> Result<Self, RS8583Error> {
+        codec.validate()?;
+        Ok(BatchReader {
+            spec,
+            codec,
+            cursor: data,
+            failed: false,
+        })
+    }
+}
+
+impl<'spec, 'c> Iterator for BatchReader<'spec, 'c> {
+    type Item = Result<Message<'spec>, RS8583Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.cursor.is_empty() {
+            return None;
+        }
+        let result = match self.codec.framing {
+            Framing::Unframed => Message::from_cursor(self.spec, self.codec, &mut self.cursor),
+            _ => loop {
+                match self.codec.split_frame(&mut self.cursor) {
+                    Ok(frame) if frame.is_empty() => {
+                        if self.cursor.is_empty() {
+                            return None;
+                        }
+                    }
+                    Ok(frame) => break Message::from_frame(self.spec, self.codec, frame),
+                    Err(err) => {
+                        self.failed = true;
+                        return Some(Err(err));
+                    }
+                }
+            },
+        };
+        if result.is_err() {
+            if let Framing::Unframed = self.codec.framing {
+                self.failed = true;
+            }
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::spec::*;
+
+    /// One fixed PROCESSING CODE field; also used by the `framed` tests.
+    pub(crate) fn batch_spec() -> MessageSpec {
+        let mut fields: Vec<Option<FieldSpec>> = (0..64).map(|_| None).collect();
+        fields[2] = Some(FieldSpec {
+            name: String::from("PROCESSING CODE"),
+            field_type: FieldType::N,
+            length: 6,
+            ..FieldSpec::default()
+        });
+        MessageSpec {
+            fields,
+            ..MessageSpec::default()
+        }
+    }
+
+    #[test]
+    fn batch_recovery() -> Result<(), RS8583Error> {
+        let spec = batch_spec();
+        let codec = Codec {
+            framing: Framing::MHeader,
+            ..Codec::default()
+        };
+        let mut data = Vec::new();
+        data.extend_from_slice(b"\x00\x120200\x04\x00\x00\x00\x00\x00\x00\x00000000");
+        data.extend_from_slice(b"\x00\x0f0200\x04\x00\x00\x00\x00\x00\x00\x00000");
+        data.extend_from_slice(b"\x00\x120210\x04\x00\x00\x00\x00\x00\x00\x00000000");
+
+        let results: Vec<_> = BatchReader::new(&spec, &codec, Bytes::from(data))?.collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().mti().as_bytes(), b"0200");
+        assert_eq!(
+            results[1].as_ref().err(),
+            Some(&RS8583Error::field_parse_error(
+                2,
+                "PROCESSING CODE",
+                RS8583Error::parse_error("Truncated field"),
+            ))
+        );
+        assert_eq!(results[2].as_ref().unwrap().mti().as_bytes(), b"0210");
+
+        Ok(())
+    }
+
+    #[test]
+    fn batch_unframed() -> Result<(), RS8583Error> {
+        let spec = batch_spec();
+        let codec = Codec::default();
+        let mut data = Vec::new();
+        data.extend_from_slice(b"0200\x04\x00\x00\x00\x00\x00\x00\x00000000");
+        data.extend_from_slice(b"0210\x04\x00\x00\x00\x00\x00\x00\x00000");
+
+        let results: Vec<_> = BatchReader::new(&spec, &codec, Bytes::from(data))?.collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn batch_vheader_keepalive() -> Result<(), RS8583Error> {
+        let spec = batch_spec();
+        let codec = Codec {
+            framing: Framing::VHeader {
+                width: 4,
+                ascii: true,
+            },
+            ..Codec::default()
+        };
+        let mut data = Vec::new();
+        data.extend_from_slice(b"0000");
+        data.extend_from_slice(b"00180200\x04\x00\x00\x00\x00\x00\x00\x00000000");
+        data.extend_from_slice(b"00000000");
+
+        let results: Vec<_> = BatchReader::new(&spec, &codec, Bytes::from(data))?.collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().mti().as_bytes(), b"0200");
+
+        Ok(())
+    }
+
+    #[test]
+    fn batch_invalid_codec() {
+        let spec = batch_spec();
+        let codec = Codec {
+            framing: Framing::VHeader {
+                width: 3,
+                ascii: true,
+            },
+            ..Codec::default()
+        };
+        assert_eq!(
+            BatchReader::new(&spec, &codec, Bytes::new()).err(),
+            Some(RS8583Error::config_error(
+                "VHeader width must be 2 or 4, not 3"
+            ))
+        );
+    }
+}
@@ -1,46 +1,128 @@
 use bitvec::prelude::*;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use encoding8::{ascii, ebcdic};
 use std::mem::size_of;
 
+use crate::codec::{BitmapEncoding, Codec};
 use crate::error::RS8583Error;
 
 type BV = BitVec<Lsb0, u64>;
 
+#[derive(Clone)]
 pub struct BitMap {
     inner: BV,
 }
 
+impl Default for BitMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BitMap {
-    pub fn from_cursor(cursor: &mut Bytes) -> Result<Self, RS8583Error> {
-        // TODO: optimize: provide default capacity != 128?
-        let mut inner = BitVec::with_capacity(128);
+    /// Empty bitmap consisting of a single primary chunk.
+    pub fn new() -> Self {
+        BitMap {
+            inner: BitVec::repeat(false, 64),
+        }
+    }
+
+    fn chunk_size(encoding: &BitmapEncoding) -> usize {
+        match encoding {
+            BitmapEncoding::Binary => size_of::<u64>(),
+            _ => size_of::<u64>() * 2,
+        }
+    }
+
+    fn read_chunk(encoding: &BitmapEncoding, cursor: &mut Bytes) -> Result<u64, RS8583Error> {
+        if cursor.remaining() < Self::chunk_size(encoding) {
+            return Err(RS8583Error::parse_error("Truncated bitmap"));
+        }
+        if let BitmapEncoding::Binary = encoding {
+            return Ok(cursor.get_u64_le());
+        }
+
+        let mut bytes = [0u8; size_of::<u64>()];
+        for byte in bytes.iter_mut() {
+            for _ in 0..2 {
+                let mut ch = cursor.get_u8();
+                if let BitmapEncoding::EBCDICHex = encoding {
+                    ch = ebcdic::to_ascii(ch);
+                }
+                let nibble = (ch as char).to_digit(16).ok_or_else(|| {
+                    RS8583Error::parse_error(format!("Invalid bitmap hex digit: 0x{:02x}", ch))
+                })?;
+                *byte = (*byte << 4) | nibble as u8;
+            }
+        }
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn from_cursor(codec: &Codec, cursor: &mut Bytes) -> Result<Self, RS8583Error> {
+        // Most messages carry the primary bitmap only; room for further
+        // chunks is made when a continuation bit announces them.
+        let mut inner = BitVec::with_capacity(64);
 
         loop {
-            if cursor.remaining() < size_of::<u64>() {
-                return Err(RS8583Error::parse_error("Truncated bitmap"));
+            let chunk_no = inner.len() / 64;
+            if codec.strict_bitmap
+                && chunk_no > 0
+                && cursor.remaining() < Self::chunk_size(&codec.bitmap_encoding)
+            {
+                return Err(RS8583Error::parse_error(format!(
+                    "Continuation bit set but bitmap chunk {} missing",
+                    chunk_no + 1
+                )));
             }
-            let mut chunk: BV = BitVec::from_element(cursor.get_u64_le());
+            let element = Self::read_chunk(&codec.bitmap_encoding, cursor)?;
+            if codec.strict_bitmap && chunk_no > 0 && element == 0 {
+                return Err(RS8583Error::parse_error(format!(
+                    "Continuation bit set but bitmap chunk {} is empty",
+                    chunk_no + 1
+                )));
+            }
+            let mut chunk: BV = BitVec::from_element(element);
             let more = chunk[0];
 
             inner.append(&mut chunk);
             if !more {
                 break;
             }
+            inner.reserve(64);
         }
 
         Ok(BitMap { inner })
     }
 
-    pub fn serialize(&self, buf: &mut BytesMut) {
+    pub fn serialize(&self, codec: &Codec, buf: &mut BytesMut) {
         for chunk in self.inner.as_slice() {
-            buf.put_u64_le(*chunk);
+            match codec.bitmap_encoding {
+                BitmapEncoding::Binary => buf.put_u64_le(*chunk),
+                ref encoding => {
+                    for byte in chunk.to_le_bytes().iter() {
+                        for ch in format!("{:02X}", byte).bytes() {
+                            if let BitmapEncoding::EBCDICHex = encoding {
+                                buf.put_u8(ascii::to_ebcdic(ch));
+                            } else {
+                                buf.put_u8(ch);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of bytes [`BitMap::serialize`] writes.
+    pub fn serialized_len(&self, codec: &Codec) -> usize {
+        match codec.bitmap_encoding {
+            BitmapEncoding::Binary => self.num_chunks() * 8,
+            BitmapEncoding::ASCIIHex | BitmapEncoding::EBCDICHex => self.num_chunks() * 16,
         }
     }
 
     fn resize_for_idx(&mut self, idx: usize) {
-        let new_size = idx + 1;
-        let new_size = new_size + (64 - new_size % 64);
-        self.inner.resize(new_size, false);
+        self.inner.resize((idx / 64 + 1) * 64, false);
     }
 
     pub fn test(&self, idx: usize) -> bool {
@@ -51,23 +133,51 @@ impl BitMap {
         }
     }
 
+    /// Set a single data bit, along with the continuation bits of preceding
+    /// chunks. Continuation bits themselves (`idx % 64 == 0`) are managed by
+    /// the bitmap, so setting one directly does nothing.
     pub fn set(&mut self, idx: usize) {
+        if idx % 64 == 0 {
+            return;
+        }
         if self.inner.len() <= idx {
             self.resize_for_idx(idx);
         }
         self.inner.set(idx, true);
-        if idx > 63 {
-            self.inner.set(idx - 64 - idx % 64, true);
+        // Every chunk before the one holding idx announces its successor
+        for chunk_no in 0..idx / 64 {
+            self.inner.set(chunk_no * 64, true);
         }
     }
 
+    /// Clear a single bit. Chunks emptied this way stay in place until
+    /// [`BitMap::compact`] is called.
     pub fn clear(&mut self, idx: usize) {
         if self.inner.len() > idx && self.inner[idx] {
             self.inner.set(idx, false);
-            // TODO: cleanup
         }
     }
 
+    /// Drop trailing chunks without data bits, and set the continuation bit
+    /// of exactly those chunks that are followed by another one.
+    pub fn compact(&mut self) {
+        let chunks = self.inner.as_slice();
+        let last = chunks
+            .iter()
+            .rposition(|chunk| chunk & !1 != 0)
+            .unwrap_or(0);
+        self.inner.truncate((last + 1) * 64);
+        for chunk_no in 0..=last {
+            self.inner.set(chunk_no * 64, chunk_no < last);
+        }
+    }
+
+    /// Indices of all set data bits, in ascending order. The first bit of
+    /// every 64-bit chunk (indices 0, 64, 128, ...) is a continuation bit and
+    /// is never yielded. Indices are what [`Message::field`] takes; for ISO
+    /// data element numbers use [`BitMap::iter_des`].
+    ///
+    /// [`Message::field`]: crate::Message::field
     pub fn iter_set(&self) -> impl Iterator<Item = usize> + '_ {
         self.inner.iter().enumerate().filter_map(|(idx, value)| {
             if idx % 64 == 0 {
@@ -79,4 +189,276 @@ impl BitMap {
             }
         })
     }
+
+    /// Data element numbers (index + 1) of all set fields, in ascending order.
+    pub fn iter_des(&self) -> impl Iterator<Item = usize> + '_ {
+        self.iter_set().map(|idx| idx + 1)
+    }
+
+    /// Write the data element numbers yielded by [`BitMap::iter_des`] into
+    /// `buf`, returning how many were written. Stops once `buf` is full.
+    pub fn collect_des_into(&self, buf: &mut [usize]) -> usize {
+        let mut count = 0;
+        for (chunk_no, chunk) in self.inner.as_slice().iter().enumerate() {
+            // Bit 0 of every chunk is a continuation bit
+            let mut bits = chunk & !1;
+            while bits != 0 {
+                if count == buf.len() {
+                    return count;
+                }
+                buf[count] = chunk_no * 64 + bits.trailing_zeros() as usize + 1;
+                count += 1;
+                bits &= bits - 1;
+            }
+        }
+        count
+    }
+
+    /// Primary bitmap in the conventional ISO 8583 bit order: the most
+    /// significant bit is the continuation bit (index 0), the next one field 1
+    /// (DE2), down to field 63 (DE64) in the least significant bit.
+    pub fn primary(&self) -> u64 {
+        self.inner.as_slice()[0].reverse_bits()
+    }
+
+    /// Secondary bitmap in the same bit order as [`BitMap::primary`], if
+    /// present. The most significant bit is the continuation bit for a
+    /// tertiary bitmap, followed by field 65 (DE66).
+    pub fn secondary(&self) -> Option<u64> {
+        self.inner
+            .as_slice()
+            .get(1)
+            .map(|chunk| chunk.reverse_bits())
+    }
+
+    /// Number of 64-bit chunks held: 1 for a primary bitmap only, 2 with a
+    /// secondary bitmap, and so on.
+    pub fn num_chunks(&self) -> usize {
+        self.inner.len() / 64
+    }
+
+    pub fn has_secondary(&self) -> bool {
+        self.num_chunks() > 1
+    }
+
+    /// Number of data bits set, not counting continuation bits.
+    pub fn count_set(&self) -> usize {
+        self.inner
+            .as_slice()
+            .iter()
+            .map(|chunk| (chunk & !1).count_ones() as usize)
+            .sum()
+    }
+
+    /// True if no data bits are set.
+    pub fn is_empty(&self) -> bool {
+        self.inner.as_slice().iter().all(|chunk| chunk & !1 == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitmap_new() {
+        let codec = Codec::default();
+        let mut bitmap = BitMap::new();
+        assert_eq!(bitmap.count_set(), 0);
+        assert!(bitmap.is_empty());
+
+        bitmap.set(2);
+        assert!(bitmap.test(2));
+        assert!(!bitmap.test(0));
+
+        let mut buf = BytesMut::new();
+        bitmap.serialize(&codec, &mut buf);
+        assert_eq!(buf.as_ref(), b"\x04\x00\x00\x00\x00\x00\x00\x00");
+
+        bitmap.clear(2);
+        assert!(!bitmap.test(2));
+    }
+
+    #[test]
+    fn bitmap_primary_secondary() {
+        let codec = Codec::default();
+        let mut bytes = Bytes::from_static(b"\x56\x00\x00\x00\x00\x00\x00\x00");
+        let bitmap = BitMap::from_cursor(&codec, &mut bytes).unwrap();
+        assert_eq!(bitmap.primary(), 0x6a00_0000_0000_0000);
+        assert_eq!(bitmap.secondary(), None);
+        assert_eq!(bitmap.count_set(), 4);
+        assert!(!bitmap.is_empty());
+        assert_eq!(bitmap.num_chunks(), 1);
+        assert!(!bitmap.has_secondary());
+        assert_eq!(bitmap.inner.len(), 64);
+
+        let mut bytes =
+            Bytes::from_static(b"\x01\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x00");
+        let bitmap = BitMap::from_cursor(&codec, &mut bytes).unwrap();
+        assert_eq!(bitmap.num_chunks(), 2);
+        assert!(bitmap.has_secondary());
+        assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![65]);
+
+        let raw = b"\x01\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x80";
+        let bitmap = BitMap::from_cursor(&codec, &mut Bytes::from_static(raw)).unwrap();
+        assert_eq!(bitmap.num_chunks(), 3);
+        assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![191]);
+        let mut buf = BytesMut::new();
+        bitmap.serialize(&codec, &mut buf);
+        assert_eq!(&buf[..], &raw[..]);
+
+        let mut bitmap = BitMap::new();
+        bitmap.set(63);
+        bitmap.set(64 + 63);
+        assert_eq!(bitmap.primary(), 0x8000_0000_0000_0001);
+        assert_eq!(bitmap.secondary(), Some(0x0000_0000_0000_0001));
+    }
+
+    #[test]
+    fn bitmap_strict_continuation() {
+        let mut codec = Codec::default();
+        let raw = b"\x01\x00\x00\x00\x00\x00\x00\x00";
+        assert_eq!(
+            BitMap::from_cursor(&codec, &mut Bytes::from_static(raw)).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Truncated bitmap"),
+            })
+        );
+
+        codec.strict_bitmap = true;
+        assert_eq!(
+            BitMap::from_cursor(&codec, &mut Bytes::from_static(raw)).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Continuation bit set but bitmap chunk 2 missing"),
+            })
+        );
+
+        let raw = b"\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        assert_eq!(
+            BitMap::from_cursor(&codec, &mut Bytes::from_static(raw)).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Continuation bit set but bitmap chunk 2 is empty"),
+            })
+        );
+
+        let raw = b"\x01\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x00";
+        let bitmap = BitMap::from_cursor(&codec, &mut Bytes::from_static(raw)).unwrap();
+        assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![65]);
+    }
+
+    #[test]
+    fn bitmap_iter_des_two_chunks() {
+        let codec = Codec::default();
+        let raw = b"\x07\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x80";
+        let bitmap = BitMap::from_cursor(&codec, &mut Bytes::from_static(raw)).unwrap();
+        assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![1, 2, 65, 127]);
+        assert_eq!(bitmap.count_set(), 4);
+        assert_eq!(bitmap.iter_des().collect::<Vec<_>>(), vec![2, 3, 66, 128]);
+    }
+
+    #[test]
+    fn bitmap_collect_des_into() {
+        let mut bitmap = BitMap::new();
+        for idx in &[1, 3, 63, 65, 127] {
+            bitmap.set(*idx);
+        }
+        let expected: Vec<usize> = bitmap.iter_des().collect();
+        assert_eq!(expected, vec![2, 4, 64, 66, 128]);
+
+        let mut buf = [0usize; 128];
+        let count = bitmap.collect_des_into(&mut buf);
+        assert_eq!(&buf[..count], &expected[..]);
+
+        let mut short = [0usize; 3];
+        assert_eq!(bitmap.collect_des_into(&mut short), 3);
+        assert_eq!(short, [2, 4, 64]);
+    }
+
+    #[test]
+    fn bitmap_iter_set_three_chunks() {
+        let codec = Codec::default();
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&(1u64 | 1 << 5).to_le_bytes());
+        raw.extend_from_slice(&(1u64 | 1 << 3).to_le_bytes());
+        raw.extend_from_slice(&(1u64 << 1 | 1 << 63).to_le_bytes());
+        let bitmap = BitMap::from_cursor(&codec, &mut Bytes::from(raw.clone())).unwrap();
+
+        assert_eq!(bitmap.num_chunks(), 3);
+        assert!(bitmap.test(0) && bitmap.test(64) && !bitmap.test(128));
+        assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![5, 67, 129, 191]);
+        assert_eq!(bitmap.count_set(), 4);
+
+        // Continuation bit 128 set, announcing an empty fourth chunk
+        raw[16] |= 1;
+        raw.extend_from_slice(&0u64.to_le_bytes());
+        let bitmap = BitMap::from_cursor(&codec, &mut Bytes::from(raw)).unwrap();
+        assert_eq!(bitmap.num_chunks(), 4);
+        assert!(bitmap.test(128));
+        assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![5, 67, 129, 191]);
+        assert_eq!(bitmap.count_set(), 4);
+    }
+
+    #[test]
+    fn bitmap_set_continuation_bits() {
+        let continuation = |bitmap: &BitMap| -> Vec<usize> {
+            (0..bitmap.num_chunks())
+                .map(|chunk_no| chunk_no * 64)
+                .filter(|idx| bitmap.test(*idx))
+                .collect()
+        };
+        let cases: &[(usize, usize, &[usize])] = &[
+            (65, 2, &[0]),
+            (127, 2, &[0]),
+            (129, 3, &[0, 64]),
+            (130, 3, &[0, 64]),
+        ];
+        for (idx, chunks, bits) in cases {
+            let mut bitmap = BitMap::new();
+            bitmap.set(*idx);
+            assert!(bitmap.test(*idx), "index {}", idx);
+            assert_eq!(bitmap.num_chunks(), *chunks, "index {}", idx);
+            assert_eq!(continuation(&bitmap), bits.to_vec(), "index {}", idx);
+        }
+
+        // Continuation bits cannot be set directly
+        for idx in &[0, 64, 128, 192] {
+            let mut bitmap = BitMap::new();
+            bitmap.set(*idx);
+            assert!(!bitmap.test(*idx), "index {}", idx);
+            assert!(bitmap.is_empty(), "index {}", idx);
+            assert_eq!(bitmap.num_chunks(), 1, "index {}", idx);
+        }
+
+        let mut bitmap = BitMap::new();
+        bitmap.set(130);
+        let mut buf = BytesMut::new();
+        bitmap.serialize(&Codec::default(), &mut buf);
+        let parsed = BitMap::from_cursor(&Codec::default(), &mut buf.freeze()).unwrap();
+        assert_eq!(parsed.iter_set().collect::<Vec<_>>(), vec![130]);
+    }
+
+    #[test]
+    fn bitmap_compact() {
+        let codec = Codec::default();
+        let mut bitmap = BitMap::new();
+        bitmap.set(2);
+        bitmap.set(130);
+        assert_eq!(bitmap.num_chunks(), 3);
+
+        bitmap.clear(130);
+        bitmap.compact();
+        assert_eq!(bitmap.num_chunks(), 1);
+        assert!(!bitmap.test(0));
+        let mut buf = BytesMut::new();
+        bitmap.serialize(&codec, &mut buf);
+        assert_eq!(buf.as_ref(), b"\x04\x00\x00\x00\x00\x00\x00\x00");
+
+        bitmap.set(70);
+        bitmap.set(130);
+        bitmap.clear(130);
+        bitmap.compact();
+        assert_eq!(bitmap.num_chunks(), 2);
+        assert!(bitmap.test(0) && !bitmap.test(64));
+        assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![2, 70]);
+    }
 }
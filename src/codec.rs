@@ -1,67 +1,399 @@
-use bytes::{BufMut, BytesMut};
-use encoding8::ascii;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use encoding8::{ascii, ebcdic};
+use std::borrow::Cow;
 
+use crate::bitmap::BitMap;
 use crate::error::RS8583Error;
+use crate::spec::FieldType;
 
+#[derive(Default)]
 pub enum Encoding {
+    #[default]
     ASCII,
     EBCDIC,
 }
 
-impl Default for Encoding {
-    fn default() -> Self {
-        Encoding::ASCII
-    }
-}
-
+/// Transport framing around each message.
+#[derive(Default)]
 pub enum Framing {
+    #[default]
     Unframed,
+    /// Two byte big-endian length of the message that follows.
     MHeader,
-    VHeader,
-}
-
-impl Default for Framing {
-    fn default() -> Self {
-        Framing::Unframed
-    }
+    /// Length header of `width` bytes (2 or 4): a big-endian binary count,
+    /// or with `ascii` that many ASCII decimal digits. Zero-length frames
+    /// are valid and carry no message, as used for keep-alives.
+    VHeader { width: usize, ascii: bool },
 }
 
+#[derive(Default)]
 pub enum VariableLengthFormat {
+    #[default]
     Symbolic,
     Byte,
+    /// Packed BCD, two digits per byte. Odd digit counts take a leading zero
+    /// nibble regardless of `Codec::bcd_pad`, so an LLLVAR length of 123 is
+    /// written `0x01 0x23` and an LLVAR length of 19 `0x19`.
+    Bcd,
+}
+
+/// Character filling the high digit positions of short symbolic length
+/// prefixes.
+#[derive(Default)]
+pub enum LengthPadding {
+    #[default]
+    Zero,
+    /// Spaces in the length encoding, e.g. "  5"; some mainframe links use
+    /// this. Zero-padded prefixes are still accepted on parse.
+    Space,
+}
+
+/// Wire representation of bitmap chunks, independent of field data encoding.
+///
+/// Hex variants carry the same eight bytes as `Binary`, written as sixteen
+/// hex digits (upper case on serialize, either case accepted on parse).
+#[derive(Default)]
+pub enum BitmapEncoding {
+    #[default]
+    Binary,
+    ASCIIHex,
+    EBCDICHex,
+}
+
+/// Wire representation of the MTI. It is always stored as four ASCII digits.
+#[derive(Default)]
+pub enum MTIEncoding {
+    #[default]
+    ASCII,
+    EBCDIC,
+    /// Two bytes of packed BCD, e.g. `0x02 0x00` for "0200".
+    BCD,
+}
+
+/// Placement of the zero pad nibble when packing an odd number of BCD
+/// digits.
+#[derive(Default)]
+pub enum BcdPad {
+    /// "123" packs as `0x01 0x23`.
+    #[default]
+    Leading,
+    /// "123" packs as `0x12 0x30`.
+    Trailing,
+}
+
+/// Relative order of the MTI and the bitmap at the start of a message.
+#[derive(Default)]
+pub enum HeaderOrder {
+    #[default]
+    MTIFirst,
+    BitmapFirst,
+}
+
+/// Handling of fields present in a message but not defined in its spec.
+#[derive(Default)]
+pub enum UnspecifiedField {
+    /// Fail, since the field cannot be encoded.
+    #[default]
+    Error,
+    /// Leave the field out, along with its bitmap bit.
+    Drop,
 }
 
-impl Default for VariableLengthFormat {
-    fn default() -> Self {
-        VariableLengthFormat::Symbolic
+/// Order in which fields are written to (and read from) the wire.
+///
+/// ISO 8583 mandates ascending field order; other strategies exist only to
+/// accommodate non-standard hosts.
+#[derive(Default)]
+pub enum FieldOrder {
+    #[default]
+    Ascending,
+    /// Listed indices first, in the given order, then all remaining set
+    /// fields in ascending order.
+    Custom(Vec<usize>),
+}
+
+impl FieldOrder {
+    pub fn order(&self, bitmap: &BitMap) -> Vec<usize> {
+        match self {
+            Self::Ascending => bitmap.iter_set().collect(),
+            Self::Custom(first) => {
+                let mut order: Vec<usize> = first
+                    .iter()
+                    .copied()
+                    .filter(|idx| bitmap.test(*idx))
+                    .collect();
+                order.extend(bitmap.iter_set().filter(|idx| !first.contains(idx)));
+                order
+            }
+        }
     }
 }
 
 #[derive(Default)]
 pub struct Codec {
     pub length_encoding: Encoding,
+    /// Wire encoding of field values other than `FieldType::B` and BCD;
+    /// parsed values are always ASCII.
     pub data_encoding: Encoding,
     pub framing: Framing,
     pub ll_format: VariableLengthFormat,
+    pub length_padding: LengthPadding,
+    pub mti_encoding: MTIEncoding,
+    pub bcd_pad: BcdPad,
+    /// Strip whitespace padding from the MTI (zero-filling from the left)
+    /// instead of rejecting it as non-numeric.
+    pub lenient_mti: bool,
+    pub bitmap_encoding: BitmapEncoding,
+    pub header_order: HeaderOrder,
+    pub field_order: FieldOrder,
+    pub unspecified_fields: UnspecifiedField,
+    /// Capture the wire bytes of every parsed field and replay them verbatim
+    /// on serialize, unless the field has been replaced since.
+    pub faithful: bool,
+    /// Validate every parsed field against its spec (length and field type
+    /// character set) and the message spec validators, failing the parse on
+    /// the first problem.
+    pub strict_field_validation: bool,
+    /// Reject bitmaps whose continuation bit announces a chunk that is
+    /// missing or has no bits set.
+    pub strict_bitmap: bool,
+    /// Append an LRC byte (XOR of all message bytes) on serialize, and verify
+    /// and strip it on parse.
+    pub lrc: bool,
+}
+
+/// Longitudinal redundancy check: XOR of all bytes of `data`.
+pub fn lrc(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, b| acc ^ b)
 }
 
 impl Codec {
+    /// Check that codec settings do not contradict each other.
+    ///
+    /// Rules:
+    /// - `length_encoding` only applies to symbolic length prefixes, so it must
+    ///   be left at the default (ASCII) with `VariableLengthFormat::Byte` and
+    ///   `VariableLengthFormat::Bcd`;
+    /// - a custom field order must not list the same index twice, nor index 0
+    ///   (the continuation bit);
+    /// - the `VHeader` width must be 2 or 4 bytes.
+    ///
+    /// Codecs are plain structs, so call this once after constructing one.
+    /// `BatchReader::new` and `framed::MessageCodec::new` do so themselves.
+    pub fn validate(&self) -> Result<(), RS8583Error> {
+        if let (VariableLengthFormat::Byte, Encoding::EBCDIC) =
+            (&self.ll_format, &self.length_encoding)
+        {
+            return Err(RS8583Error::config_error(
+                "EBCDIC length encoding is meaningless with binary length format",
+            ));
+        }
+        if let (VariableLengthFormat::Bcd, Encoding::EBCDIC) =
+            (&self.ll_format, &self.length_encoding)
+        {
+            return Err(RS8583Error::config_error(
+                "EBCDIC length encoding is meaningless with BCD length format",
+            ));
+        }
+        if let Framing::VHeader { width, .. } = self.framing {
+            Self::check_vheader_width(width)?;
+        }
+        if let FieldOrder::Custom(order) = &self.field_order {
+            for (pos, idx) in order.iter().enumerate() {
+                if *idx == 0 {
+                    return Err(RS8583Error::config_error(
+                        "Custom field order must not contain index 0",
+                    ));
+                }
+                if order[..pos].contains(idx) {
+                    return Err(RS8583Error::config_error(format!(
+                        "Custom field order lists index {} more than once",
+                        idx
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_vheader_width(width: usize) -> Result<(), RS8583Error> {
+        if width != 2 && width != 4 {
+            return Err(RS8583Error::config_error(format!(
+                "VHeader width must be 2 or 4, not {}",
+                width
+            )));
+        }
+        Ok(())
+    }
+
+    /// Message length announced by the frame header at the start of `data`,
+    /// or `None` if `data` does not hold the whole header yet. Unframed data
+    /// is taken as a single message.
+    pub fn peek_frame_len(&self, data: &[u8]) -> Result<Option<usize>, RS8583Error> {
+        if let Framing::VHeader { width, .. } = self.framing {
+            Self::check_vheader_width(width)?;
+        }
+        let header_len = self.frame_header_len();
+        if data.len() < header_len {
+            return Ok(None);
+        }
+        let header = &data[..header_len];
+        let len = match self.framing {
+            Framing::Unframed => data.len(),
+            Framing::MHeader => u16::from_be_bytes([header[0], header[1]]) as usize,
+            Framing::VHeader { ascii: true, .. } => {
+                header.iter().try_fold(0, |len, b| match b {
+                    b'0'..=b'9' => Ok(len * 10 + (b - b'0') as usize),
+                    _ => Err(RS8583Error::parse_error(format!(
+                        "Invalid frame header digit: 0x{:02x}",
+                        b
+                    ))),
+                })?
+            }
+            Framing::VHeader { ascii: false, .. } => {
+                header.iter().fold(0, |len, b| (len << 8) | *b as usize)
+            }
+        };
+        Ok(Some(len))
+    }
+
+    /// Split the next frame off `cursor`, returning the message bytes without
+    /// the framing header. Unframed input is returned as a single frame.
+    pub fn split_frame(&self, cursor: &mut Bytes) -> Result<Bytes, RS8583Error> {
+        let len = self
+            .peek_frame_len(cursor)?
+            .ok_or_else(|| RS8583Error::parse_error("Truncated frame header"))?;
+        cursor.advance(self.frame_header_len());
+        if cursor.remaining() < len {
+            return Err(RS8583Error::parse_error(format!(
+                "Truncated frame ({} bytes declared, {} available)",
+                len,
+                cursor.remaining()
+            )));
+        }
+        Ok(cursor.split_to(len))
+    }
+
+    /// Number of bytes taken by the frame header.
+    pub fn frame_header_len(&self) -> usize {
+        match self.framing {
+            Framing::Unframed => 0,
+            Framing::MHeader => 2,
+            Framing::VHeader { width, .. } => width,
+        }
+    }
+
+    /// Write the framing header for a message of `len` bytes.
+    pub fn serialize_frame_header(
+        &self,
+        buf: &mut BytesMut,
+        len: usize,
+    ) -> Result<(), RS8583Error> {
+        match self.framing {
+            Framing::Unframed => Ok(()),
+            Framing::MHeader => {
+                if len > u16::MAX as usize {
+                    return Err(RS8583Error::parse_error(format!(
+                        "Message too long for frame header: {}",
+                        len
+                    )));
+                }
+                buf.put_u16(len as u16);
+                Ok(())
+            }
+            Framing::VHeader { width, ascii } => {
+                Self::check_vheader_width(width)?;
+                let max = if ascii {
+                    10usize.pow(width as u32) - 1
+                } else {
+                    (1usize << (8 * width)) - 1
+                };
+                if len > max {
+                    return Err(RS8583Error::parse_error(format!(
+                        "Message too long for frame header: {}",
+                        len
+                    )));
+                }
+                if ascii {
+                    buf.extend_from_slice(format!("{:01$}", len, width).as_bytes());
+                } else {
+                    buf.extend_from_slice(&(len as u32).to_be_bytes()[4 - width..]);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Transcode wire data of a field to ASCII according to `data_encoding`.
+    /// Binary fields are never transcoded.
+    pub fn decode_data(&self, field_type: FieldType, data: Bytes) -> Bytes {
+        match (&self.data_encoding, field_type) {
+            (Encoding::ASCII, _) | (_, FieldType::B) => data,
+            (Encoding::EBCDIC, _) => data.iter().map(|b| ebcdic::to_ascii(*b)).collect(),
+        }
+    }
+
+    /// Inverse of [`Codec::decode_data`].
+    pub fn encode_data<'a>(&self, field_type: FieldType, data: &'a [u8]) -> Cow<'a, [u8]> {
+        match (&self.data_encoding, field_type) {
+            (Encoding::ASCII, _) | (_, FieldType::B) => Cow::Borrowed(data),
+            (Encoding::EBCDIC, _) => {
+                Cow::Owned(data.iter().map(|b| ascii::to_ebcdic(*b)).collect())
+            }
+        }
+    }
+
     pub fn length_size_bytes(&self, len: usize) -> usize {
         match self.ll_format {
             VariableLengthFormat::Symbolic => len,
             VariableLengthFormat::Byte => 1,
+            VariableLengthFormat::Bcd => len.div_ceil(2),
         }
     }
 
+    /// Pad byte accepted (and written) in the high positions of symbolic
+    /// length prefixes in place of zeros, if any.
+    pub fn length_pad_byte(&self) -> Option<u8> {
+        match (&self.ll_format, &self.length_padding) {
+            (VariableLengthFormat::Symbolic, LengthPadding::Space) => match self.length_encoding {
+                Encoding::ASCII => Some(b' '),
+                Encoding::EBCDIC => Some(ascii::to_ebcdic(b' ')),
+            },
+            _ => None,
+        }
+    }
+
+    /// Value of a single length prefix byte: one digit for symbolic
+    /// prefixes, two for BCD.
     pub fn byte_to_length(&self, len_byte: u8) -> Result<usize, RS8583Error> {
-        if let VariableLengthFormat::Byte = self.ll_format {
-            return Ok(len_byte as usize);
+        match self.ll_format {
+            VariableLengthFormat::Byte => Ok(len_byte as usize),
+            VariableLengthFormat::Bcd if len_byte >> 4 > 9 || len_byte & 0x0f > 9 => Err(
+                RS8583Error::parse_error(format!("Invalid BCD length byte: 0x{:02x}", len_byte)),
+            ),
+            VariableLengthFormat::Bcd => {
+                Ok((len_byte >> 4) as usize * 10 + (len_byte & 0x0f) as usize)
+            }
+            VariableLengthFormat::Symbolic => self.digit_value(len_byte),
         }
+    }
+
+    /// Number base of successive length prefix bytes.
+    pub fn length_byte_radix(&self) -> usize {
+        match self.ll_format {
+            VariableLengthFormat::Symbolic => 10,
+            VariableLengthFormat::Byte => 256,
+            VariableLengthFormat::Bcd => 100,
+        }
+    }
+
+    /// Value of a single symbolic digit in the length encoding.
+    pub fn digit_value(&self, digit: u8) -> Result<usize, RS8583Error> {
         let offset: u8 = match self.length_encoding {
             Encoding::ASCII => 0x30,
             Encoding::EBCDIC => 0xf0,
         };
-        match len_byte {
+        match digit {
             n if n > (offset + 9) => Err(RS8583Error::parse_error(format!(
                 "Length byte out of range: 0x{:02x}",
                 n
@@ -82,7 +414,7 @@ impl Codec {
     ) -> Result<(), RS8583Error> {
         match self.ll_format {
             VariableLengthFormat::Byte => {
-                if data_len > (std::u8::MAX as usize) {
+                if data_len > (u8::MAX as usize) {
                     Err(RS8583Error::parse_error(format!(
                         "Length out of range: {}",
                         data_len
@@ -92,17 +424,329 @@ impl Codec {
                     Ok(())
                 }
             }
+            VariableLengthFormat::Bcd => {
+                if data_len >= 10usize.pow(prefix_len as u32) {
+                    return Err(RS8583Error::parse_error(format!(
+                        "Length out of range: {} does not fit in {} digits",
+                        data_len, prefix_len
+                    )));
+                }
+                let width = prefix_len + prefix_len % 2;
+                let digits = format!("{0:01$}", data_len, width);
+                buf.extend(
+                    digits
+                        .as_bytes()
+                        .chunks(2)
+                        .map(|pair| ((pair[0] - b'0') << 4) | (pair[1] - b'0')),
+                );
+                Ok(())
+            }
             VariableLengthFormat::Symbolic => {
-                // TODO: efficiency
-                let mut prefix = format!("{0:01$}", data_len, prefix_len).into_bytes();
-                if let Encoding::EBCDIC = self.length_encoding {
-                    for ch in prefix.iter_mut() {
-                        *ch = ascii::to_ebcdic(*ch);
+                let start = buf.len();
+                self.serialize_digits(buf, prefix_len, data_len)?;
+                if let Some(pad) = self.length_pad_byte() {
+                    let zero = match self.length_encoding {
+                        Encoding::ASCII => b'0',
+                        Encoding::EBCDIC => ascii::to_ebcdic(b'0'),
+                    };
+                    let end = buf.len().saturating_sub(1);
+                    for ch in buf[start..end].iter_mut().take_while(|ch| **ch == zero) {
+                        *ch = pad;
                     }
                 }
-                buf.extend_from_slice(&prefix);
                 Ok(())
             }
         }
     }
+
+    /// Write `value` as `width` zero-padded symbolic digits in the length
+    /// encoding.
+    pub fn serialize_digits(
+        &self,
+        buf: &mut BytesMut,
+        width: usize,
+        value: usize,
+    ) -> Result<(), RS8583Error> {
+        if value >= 10usize.pow(width as u32) {
+            return Err(RS8583Error::parse_error(format!(
+                "Length out of range: {} does not fit in {} digits",
+                value, width
+            )));
+        }
+        // TODO: efficiency
+        let mut digits = format!("{0:01$}", value, width).into_bytes();
+        if let Encoding::EBCDIC = self.length_encoding {
+            for ch in digits.iter_mut() {
+                *ch = ascii::to_ebcdic(*ch);
+            }
+        }
+        buf.extend_from_slice(&digits);
+        Ok(())
+    }
+
+    /// Pack ASCII digits as BCD, two digits per byte. An odd number of digits
+    /// is padded with a zero nibble placed according to `bcd_pad`.
+    pub fn pack_bcd(&self, digits: &[u8]) -> Result<Vec<u8>, RS8583Error> {
+        let mut nibbles = Vec::with_capacity(digits.len() + 1);
+        let odd = digits.len() % 2 != 0;
+        if odd {
+            if let BcdPad::Leading = self.bcd_pad {
+                nibbles.push(0);
+            }
+        }
+        for digit in digits {
+            if !digit.is_ascii_digit() {
+                return Err(RS8583Error::parse_error(format!(
+                    "Invalid BCD digit: 0x{:02x}",
+                    digit
+                )));
+            }
+            nibbles.push(digit - b'0');
+        }
+        if odd {
+            if let BcdPad::Trailing = self.bcd_pad {
+                nibbles.push(0);
+            }
+        }
+        Ok(nibbles
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect())
+    }
+
+    /// Unpack `digits` BCD digits from `data` into ASCII, dropping the pad
+    /// nibble of an odd digit count as placed by `bcd_pad`. The pad nibble
+    /// must be 0 or F.
+    pub fn unpack_bcd(&self, data: &[u8], digits: usize) -> Result<Vec<u8>, RS8583Error> {
+        let nibbles = data.len() * 2;
+        let pad = nibbles.saturating_sub(digits);
+        let mut out = Vec::with_capacity(nibbles);
+        for pos in 0..nibbles {
+            let byte = data[pos / 2];
+            let nibble = if pos % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            let is_pad = match self.bcd_pad {
+                BcdPad::Leading => pos < pad,
+                BcdPad::Trailing => pos >= digits,
+            };
+            if is_pad {
+                if nibble != 0 && nibble != 0x0f {
+                    return Err(RS8583Error::parse_error(format!(
+                        "Invalid BCD pad nibble in byte 0x{:02x}",
+                        byte
+                    )));
+                }
+            } else if nibble > 9 {
+                return Err(RS8583Error::parse_error(format!(
+                    "Invalid BCD byte: 0x{:02x}",
+                    byte
+                )));
+            } else {
+                out.push(b'0' + nibble);
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec_split_frame() {
+        let codec = Codec {
+            framing: Framing::MHeader,
+            ..Codec::default()
+        };
+        let mut data = Bytes::from_static(b"\x00\x03ABC\x00\x05DE");
+        assert_eq!(codec.split_frame(&mut data), Ok(Bytes::from_static(b"ABC")));
+        assert_eq!(
+            codec.split_frame(&mut data),
+            Err(RS8583Error::ParseError {
+                error: String::from("Truncated frame (5 bytes declared, 2 available)"),
+            })
+        );
+    }
+
+    #[test]
+    fn codec_vheader_frame() {
+        let ascii = Codec {
+            framing: Framing::VHeader {
+                width: 4,
+                ascii: true,
+            },
+            ..Codec::default()
+        };
+        let mut buf = BytesMut::new();
+        ascii.serialize_frame_header(&mut buf, 3).unwrap();
+        buf.extend_from_slice(b"ABC");
+        ascii.serialize_frame_header(&mut buf, 0).unwrap();
+        assert_eq!(buf.as_ref(), b"0003ABC0000");
+
+        let mut data = buf.freeze();
+        assert_eq!(ascii.split_frame(&mut data), Ok(Bytes::from_static(b"ABC")));
+        assert_eq!(ascii.split_frame(&mut data), Ok(Bytes::new()));
+        assert!(data.is_empty());
+
+        let binary = Codec {
+            framing: Framing::VHeader {
+                width: 4,
+                ascii: false,
+            },
+            ..Codec::default()
+        };
+        let mut buf = BytesMut::new();
+        binary.serialize_frame_header(&mut buf, 0x0102).unwrap();
+        assert_eq!(buf.as_ref(), b"\x00\x00\x01\x02");
+        assert_eq!(
+            binary.split_frame(&mut Bytes::from_static(b"\x00\x00\x00\x09ABC")),
+            Err(RS8583Error::ParseError {
+                error: String::from("Truncated frame (9 bytes declared, 3 available)"),
+            })
+        );
+
+        let narrow = Codec {
+            framing: Framing::VHeader {
+                width: 2,
+                ascii: true,
+            },
+            ..Codec::default()
+        };
+        assert_eq!(
+            narrow.serialize_frame_header(&mut BytesMut::new(), 100),
+            Err(RS8583Error::ParseError {
+                error: String::from("Message too long for frame header: 100"),
+            })
+        );
+        assert_eq!(
+            narrow.split_frame(&mut Bytes::from_static(b"0xABC")),
+            Err(RS8583Error::ParseError {
+                error: String::from("Invalid frame header digit: 0x78"),
+            })
+        );
+
+        // Unvalidated wide header: rejected rather than overflowing the length
+        let wide = Codec {
+            framing: Framing::VHeader {
+                width: 24,
+                ascii: true,
+            },
+            ..Codec::default()
+        };
+        let header = [b'9'; 24];
+        assert_eq!(
+            wide.peek_frame_len(&header),
+            Err(RS8583Error::config_error(
+                "VHeader width must be 2 or 4, not 24"
+            ))
+        );
+        assert!(wide
+            .split_frame(&mut Bytes::copy_from_slice(&header))
+            .is_err());
+    }
+
+    #[test]
+    fn codec_space_padded_prefix() {
+        let codec = Codec {
+            length_encoding: Encoding::EBCDIC,
+            length_padding: LengthPadding::Space,
+            ..Codec::default()
+        };
+        let mut buf = BytesMut::new();
+        codec.serialize_prefix(&mut buf, 3, 5).unwrap();
+        codec.serialize_prefix(&mut buf, 3, 0).unwrap();
+        codec.serialize_prefix(&mut buf, 3, 105).unwrap();
+        assert_eq!(buf.as_ref(), b"\x40\x40\xf5\x40\x40\xf0\xf1\xf0\xf5");
+    }
+
+    #[test]
+    fn codec_validate() {
+        assert_eq!(Codec::default().validate(), Ok(()));
+
+        let codec = Codec {
+            length_encoding: Encoding::EBCDIC,
+            ll_format: VariableLengthFormat::Byte,
+            ..Codec::default()
+        };
+        assert_eq!(
+            codec.validate(),
+            Err(RS8583Error::ConfigError {
+                error: String::from(
+                    "EBCDIC length encoding is meaningless with binary length format"
+                ),
+            })
+        );
+
+        let codec = Codec {
+            field_order: FieldOrder::Custom(vec![4, 2, 4]),
+            ..Codec::default()
+        };
+        assert_eq!(
+            codec.validate(),
+            Err(RS8583Error::ConfigError {
+                error: String::from("Custom field order lists index 4 more than once"),
+            })
+        );
+
+        let codec = Codec {
+            framing: Framing::VHeader {
+                width: 3,
+                ascii: false,
+            },
+            ..Codec::default()
+        };
+        assert_eq!(
+            codec.validate(),
+            Err(RS8583Error::ConfigError {
+                error: String::from("VHeader width must be 2 or 4, not 3"),
+            })
+        );
+    }
+
+    #[test]
+    fn codec_bcd_round_trip() {
+        let codec = Codec::default();
+        assert_eq!(codec.pack_bcd(b"1234"), Ok(vec![0x12, 0x34]));
+        assert_eq!(codec.unpack_bcd(&[0x12, 0x34], 4), Ok(b"1234".to_vec()));
+        assert_eq!(codec.pack_bcd(b"123"), Ok(vec![0x01, 0x23]));
+        assert_eq!(codec.unpack_bcd(&[0x01, 0x23], 3), Ok(b"123".to_vec()));
+        assert_eq!(
+            codec.pack_bcd(b"12A4"),
+            Err(RS8583Error::parse_error("Invalid BCD digit: 0x41"))
+        );
+    }
+
+    #[test]
+    fn codec_bcd_pad() {
+        let leading = Codec::default();
+        assert_eq!(leading.pack_bcd(b"003"), Ok(vec![0x00, 0x03]));
+        assert_eq!(leading.unpack_bcd(&[0x00, 0x03], 3), Ok(b"003".to_vec()));
+        assert_eq!(leading.unpack_bcd(&[0xf0, 0x03], 3), Ok(b"003".to_vec()));
+        assert_eq!(
+            leading.unpack_bcd(&[0x50, 0x03], 3),
+            Err(RS8583Error::parse_error(
+                "Invalid BCD pad nibble in byte 0x50"
+            ))
+        );
+        assert_eq!(
+            leading.unpack_bcd(&[0x0a, 0x03], 3),
+            Err(RS8583Error::parse_error("Invalid BCD byte: 0x0a"))
+        );
+
+        let trailing = Codec {
+            bcd_pad: BcdPad::Trailing,
+            ..Codec::default()
+        };
+        assert_eq!(trailing.pack_bcd(b"003"), Ok(vec![0x00, 0x30]));
+        assert_eq!(trailing.unpack_bcd(&[0x00, 0x30], 3), Ok(b"003".to_vec()));
+        assert_eq!(trailing.unpack_bcd(&[0x00, 0x3f], 3), Ok(b"003".to_vec()));
+        assert_eq!(
+            trailing.unpack_bcd(&[0x00, 0x35], 3),
+            Err(RS8583Error::parse_error(
+                "Invalid BCD pad nibble in byte 0x35"
+            ))
+        );
+        assert_eq!(trailing.pack_bcd(b"1234"), Ok(vec![0x12, 0x34]));
+        assert_eq!(trailing.unpack_bcd(&[0x12, 0x34], 4), Ok(b"1234".to_vec()));
+    }
 }
@@ -0,0 +1,1693 @@
+//! Typed accessors for well-known data elements.
+//!
+//! Field indices follow the crate-wide convention: index `n` holds ISO 8583
+//! data element `n + 1`, index 0 being the continuation bit.
+//!
+//! With the `derive` feature, `#[derive(DataElements)]` maps a struct onto
+//! message fields. Each struct field is annotated with its data element
+//! number (not index), and its type must implement [`FieldValue`]; wrap it in
+//! `Option` if the element is not mandatory:
+//!
+//! ```ignore
+//! #[derive(DataElements)]
+//! struct Purchase {
+//!     #[de(2)]
+//!     pan: String,
+//!     #[de(4)]
+//!     amount: u64,
+//!     #[de(11)]
+//!     stan: Option<u32>,
+//! }
+//!
+//! let purchase = Purchase::from_message(&msg)?;
+//! purchase.to_message(&mut reply)?;
+//! ```
+
+use bytes::Bytes;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, TimeZone, Utc};
+use std::cmp::min;
+use std::collections::BTreeMap;
+
+use crate::codec::Codec;
+
+use crate::error::RS8583Error;
+use crate::field::Field;
+#[cfg(feature = "iso4217")]
+use crate::iso4217;
+use crate::msg::Message;
+use crate::spec::{FieldSpec, LengthType, SensitivityType};
+use crate::subfield;
+
+pub const PAN: usize = 1;
+pub const AMOUNT_TRANSACTION: usize = 3;
+pub const AMOUNT_SETTLEMENT: usize = 4;
+pub const AMOUNT_CARDHOLDER_BILLING: usize = 5;
+pub const TRANSMISSION_DATETIME: usize = 6;
+pub const CONVERSION_RATE_SETTLEMENT: usize = 8;
+pub const CONVERSION_RATE_CARDHOLDER_BILLING: usize = 9;
+pub const STAN: usize = 10;
+pub const LOCAL_TIME: usize = 11;
+pub const LOCAL_DATE: usize = 12;
+pub const POS_ENTRY_MODE: usize = 21;
+pub const ACQUIRING_INSTITUTION_ID: usize = 31;
+pub const FORWARDING_INSTITUTION_ID: usize = 32;
+pub const RETRIEVAL_REFERENCE_NUMBER: usize = 36;
+pub const AUTHORIZATION_ID_RESPONSE: usize = 37;
+pub const RESPONSE_CODE: usize = 38;
+pub const CARD_ACCEPTOR_TERMINAL_ID: usize = 40;
+pub const CARD_ACCEPTOR_ID: usize = 41;
+pub const TRACK_1_DATA: usize = 44;
+pub const ADDITIONAL_DATA: usize = 47;
+pub const TRANSACTION_CURRENCY_CODE: usize = 48;
+pub const BILLING_CURRENCY_CODE: usize = 50;
+pub const PIN_DATA: usize = 51;
+pub const SECURITY_CONTROL: usize = 52;
+pub const VISA_POS_DATA: usize = 59;
+pub const MASTERCARD_POS_DATA: usize = 60;
+pub const ORIGINAL_DATA_ELEMENTS: usize = 89;
+
+const RESPONSE_CODES: &[(&str, &str)] = &[
+    ("00", "Approved or completed successfully"),
+    ("01", "Refer to card issuer"),
+    ("02", "Refer to card issuer, special condition"),
+    ("03", "Invalid merchant"),
+    ("04", "Pick up card"),
+    ("05", "Do not honor"),
+    ("06", "Error"),
+    ("07", "Pick up card, special condition"),
+    ("08", "Honor with identification"),
+    ("09", "Request in progress"),
+    ("10", "Approved for partial amount"),
+    ("11", "Approved (VIP)"),
+    ("12", "Invalid transaction"),
+    ("13", "Invalid amount"),
+    ("14", "Invalid card number"),
+    ("15", "No such issuer"),
+    ("16", "Approved, update track 3"),
+    ("17", "Customer cancellation"),
+    ("18", "Customer dispute"),
+    ("19", "Re-enter transaction"),
+    ("20", "Invalid response"),
+    ("21", "No action taken"),
+    ("22", "Suspected malfunction"),
+    ("23", "Unacceptable transaction fee"),
+    ("24", "File update not supported by receiver"),
+    ("25", "Unable to locate record on file"),
+    ("26", "Duplicate file update record, old record replaced"),
+    ("27", "File update field edit error"),
+    ("28", "File update file locked out"),
+    ("29", "File update not successful, contact acquirer"),
+    ("30", "Format error"),
+    ("31", "Bank not supported by switch"),
+    ("32", "Completed partially"),
+    ("33", "Expired card, pick up"),
+    ("34", "Suspected fraud, pick up"),
+    ("35", "Card acceptor contact acquirer, pick up"),
+    ("36", "Restricted card, pick up"),
+    ("37", "Card acceptor call acquirer security, pick up"),
+    ("38", "Allowable PIN tries exceeded, pick up"),
+    ("39", "No credit account"),
+    ("40", "Requested function not supported"),
+    ("41", "Lost card, pick up"),
+    ("42", "No universal account"),
+    ("43", "Stolen card, pick up"),
+    ("44", "No investment account"),
+    ("51", "Not sufficient funds"),
+    ("52", "No checking account"),
+    ("53", "No savings account"),
+    ("54", "Expired card"),
+    ("55", "Incorrect PIN"),
+    ("56", "No card record"),
+    ("57", "Transaction not permitted to cardholder"),
+    ("58", "Transaction not permitted to terminal"),
+    ("59", "Suspected fraud"),
+    ("60", "Card acceptor contact acquirer"),
+    ("61", "Exceeds withdrawal amount limit"),
+    ("62", "Restricted card"),
+    ("63", "Security violation"),
+    ("64", "Original amount incorrect"),
+    ("65", "Exceeds withdrawal frequency limit"),
+    ("66", "Card acceptor call acquirer's security department"),
+    ("67", "Hard capture, pick up card at ATM"),
+    ("68", "Response received too late"),
+    ("75", "Allowable number of PIN tries exceeded"),
+    ("90", "Cutoff is in process"),
+    ("91", "Issuer or switch is inoperative"),
+    (
+        "92",
+        "Financial institution or intermediate network facility cannot be found",
+    ),
+    ("93", "Transaction cannot be completed, violation of law"),
+    ("94", "Duplicate transmission"),
+    ("95", "Reconcile error"),
+    ("96", "System malfunction"),
+];
+
+/// Description of a standard ISO 8583:1987 response code (DE39).
+pub fn response_code_description(code: &str) -> Option<&'static str> {
+    RESPONSE_CODES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, descr)| *descr)
+}
+
+fn numeric_str(idx: usize, field: &Field) -> Result<&str, RS8583Error> {
+    let data = field.as_slice();
+    if !data.iter().all(u8::is_ascii_digit) {
+        return Err(RS8583Error::parse_error(format!(
+            "field {}: non-numeric value",
+            idx
+        )));
+    }
+    // All bytes are ASCII digits at this point
+    Ok(std::str::from_utf8(data).unwrap())
+}
+
+fn set_numeric<T>(msg: &mut Message, idx: usize, value: T) -> Result<(), RS8583Error>
+where
+    T: Into<Bytes>,
+{
+    let value = value.into();
+    numeric_str(idx, &Field::from_bytes(value.clone()))?;
+    msg.set_field(idx, value)
+}
+
+fn set_fixed_alphanumeric(
+    msg: &mut Message,
+    idx: usize,
+    value: &str,
+    len: usize,
+) -> Result<(), RS8583Error> {
+    if value.len() != len || !value.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return Err(RS8583Error::parse_error(format!(
+            "field {}: expected exactly {} alphanumeric characters",
+            idx, len
+        )));
+    }
+    msg.set_field(idx, Bytes::copy_from_slice(value.as_bytes()))
+}
+
+/// Store `value` in fixed field `idx`, right-padded with spaces to `width`.
+fn set_space_padded(
+    msg: &mut Message,
+    idx: usize,
+    value: &str,
+    width: usize,
+) -> Result<(), RS8583Error> {
+    if value.len() > width {
+        return Err(RS8583Error::parse_error(format!(
+            "field {}: value longer than {} characters",
+            idx, width
+        )));
+    }
+    msg.set_field(idx, format!("{:<1$}", value, width))
+}
+
+/// Interpret `MMDDhhmmss` digits of field `idx` as a point in time in `tz`.
+#[cfg(feature = "chrono")]
+fn parse_datetime<Tz: TimeZone>(
+    idx: usize,
+    digits: &str,
+    tz: &Tz,
+    year: i32,
+) -> Result<DateTime<Tz>, RS8583Error> {
+    let invalid = || RS8583Error::parse_error(format!("field {}: invalid date/time", idx));
+    if digits.len() != 10 {
+        return Err(invalid());
+    }
+    let part = |pos: usize| digits[pos..pos + 2].parse::<u32>().unwrap();
+    tz.with_ymd_and_hms(year, part(0), part(2), part(4), part(6), part(8))
+        .single()
+        .ok_or_else(invalid)
+}
+
+/// Conversion rate (DE9, DE10): the leftmost digit of the field gives the
+/// number of decimal places in the remaining seven digits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConversionRate {
+    pub decimals: u32,
+    pub mantissa: u32,
+}
+
+impl ConversionRate {
+    fn from_field(idx: usize, field: &Field) -> Result<Self, RS8583Error> {
+        let digits = numeric_str(idx, field)?;
+        if digits.len() != 8 {
+            return Err(RS8583Error::parse_error(format!(
+                "field {}: conversion rate must be 8 digits",
+                idx
+            )));
+        }
+        Ok(ConversionRate {
+            decimals: digits[..1].parse().unwrap(),
+            mantissa: digits[1..].parse().unwrap(),
+        })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        f64::from(self.mantissa) / 10f64.powi(self.decimals as i32)
+    }
+}
+
+impl std::fmt::Display for ConversionRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let scale = 10u32.pow(self.decimals);
+        if self.decimals == 0 {
+            write!(f, "{}", self.mantissa)
+        } else {
+            write!(
+                f,
+                "{}.{:02$}",
+                self.mantissa / scale,
+                self.mantissa % scale,
+                self.decimals as usize
+            )
+        }
+    }
+}
+
+/// Card scheme, selecting scheme-specific field layouts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CardScheme {
+    Visa,
+    Mastercard,
+    Amex,
+    Discover,
+    Jcb,
+    UnionPay,
+}
+
+/// PANs whose leading digits fall within `low..=high` belong to `scheme`.
+/// Both bounds must have the same number of digits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BinRange {
+    pub low: String,
+    pub high: String,
+    pub scheme: CardScheme,
+}
+
+impl BinRange {
+    fn matches(&self, pan: &str) -> bool {
+        pan.get(..self.low.len())
+            .is_some_and(|prefix| (self.low.as_str()..=self.high.as_str()).contains(&prefix))
+    }
+}
+
+/// BIN ranges used to classify PANs by scheme; the first matching range
+/// wins. The default table covers the major networks, but ranges change, so
+/// it is meant to be adjusted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BinTable {
+    pub ranges: Vec<BinRange>,
+}
+
+impl Default for BinTable {
+    fn default() -> Self {
+        let ranges = [
+            ("4", "4", CardScheme::Visa),
+            ("51", "55", CardScheme::Mastercard),
+            ("2221", "2720", CardScheme::Mastercard),
+            ("34", "34", CardScheme::Amex),
+            ("37", "37", CardScheme::Amex),
+            ("6011", "6011", CardScheme::Discover),
+            ("644", "649", CardScheme::Discover),
+            ("65", "65", CardScheme::Discover),
+            ("3528", "3589", CardScheme::Jcb),
+            ("62", "62", CardScheme::UnionPay),
+        ];
+        BinTable {
+            ranges: ranges
+                .iter()
+                .map(|(low, high, scheme)| BinRange {
+                    low: String::from(*low),
+                    high: String::from(*high),
+                    scheme: *scheme,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl BinTable {
+    pub fn classify(&self, pan: &str) -> Option<CardScheme> {
+        self.ranges
+            .iter()
+            .find(|range| range.matches(pan))
+            .map(|range| range.scheme)
+    }
+}
+
+/// Positional subfields: name and width.
+type Layout = &'static [(&'static str, usize)];
+
+/// Visa DE60, additional POS information.
+const VISA_POS_LAYOUT: Layout = &[
+    ("terminal_type", 1),
+    ("terminal_entry_capability", 1),
+    ("chip_condition_code", 1),
+    ("special_condition_indicator", 1),
+    ("merchant_group_indicator", 2),
+    ("chip_transaction_indicator", 1),
+    ("chip_authentication_reliability", 1),
+    ("ecommerce_indicator", 2),
+    ("cardholder_id_method", 1),
+    ("additional_authorization_indicator", 1),
+];
+
+/// Mastercard DE61, point-of-service data.
+const MASTERCARD_POS_LAYOUT: Layout = &[
+    ("terminal_attendance", 1),
+    ("reserved_2", 1),
+    ("terminal_location", 1),
+    ("cardholder_presence", 1),
+    ("card_presence", 1),
+    ("card_capture_capability", 1),
+    ("transaction_status", 1),
+    ("transaction_security", 1),
+    ("reserved_9", 1),
+    ("cardholder_activated_terminal_level", 1),
+    ("card_data_terminal_input_capability", 1),
+    ("authorization_life_cycle", 2),
+    ("country_code", 3),
+    ("postal_code", 10),
+];
+
+/// POS data decoded into named positional subfields. Trailing subfields
+/// absent from the data are omitted; the last one present may be short.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PosData<'a> {
+    pub subfields: Vec<(&'static str, &'a str)>,
+}
+
+impl<'a> PosData<'a> {
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.subfields
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Field index and subfield layout of the POS data of `scheme`.
+fn pos_data_layout(scheme: CardScheme) -> Result<(usize, Layout), RS8583Error> {
+    match scheme {
+        CardScheme::Visa => Ok((VISA_POS_DATA, VISA_POS_LAYOUT)),
+        CardScheme::Mastercard => Ok((MASTERCARD_POS_DATA, MASTERCARD_POS_LAYOUT)),
+        _ => Err(RS8583Error::parse_error(format!(
+            "No POS data layout for {:?}",
+            scheme
+        ))),
+    }
+}
+
+/// Split POS data according to the layout used by `scheme`.
+pub fn parse_pos_data(scheme: CardScheme, data: &[u8]) -> Result<PosData<'_>, RS8583Error> {
+    let (_, layout) = pos_data_layout(scheme)?;
+    let data = std::str::from_utf8(data)
+        .ok()
+        .filter(|data| data.is_ascii())
+        .ok_or_else(|| RS8583Error::parse_error("Invalid POS data: non-ASCII"))?;
+    let mut subfields = Vec::new();
+    let mut pos = 0;
+    for (name, width) in layout {
+        if pos >= data.len() {
+            break;
+        }
+        let end = min(pos + width, data.len());
+        subfields.push((*name, &data[pos..end]));
+        pos = end;
+    }
+    if pos < data.len() {
+        return Err(RS8583Error::parse_error(format!(
+            "Invalid POS data: {} bytes past the end of the layout",
+            data.len() - pos
+        )));
+    }
+    Ok(PosData { subfields })
+}
+
+/// Original data elements (DE90), identifying the transaction a reversal
+/// refers to. Institution IDs are zero-padded to 11 digits on the wire.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OriginalData {
+    pub mti: String,
+    pub stan: String,
+    pub transmission_datetime: String,
+    pub acquiring_institution_id: String,
+    pub forwarding_institution_id: String,
+}
+
+impl OriginalData {
+    fn from_field(idx: usize, field: &Field) -> Result<Self, RS8583Error> {
+        let digits = numeric_str(idx, field)?;
+        if digits.len() != 42 {
+            return Err(RS8583Error::parse_error(format!(
+                "field {}: original data elements must be 42 digits",
+                idx
+            )));
+        }
+        Ok(OriginalData {
+            mti: String::from(&digits[..4]),
+            stan: String::from(&digits[4..10]),
+            transmission_datetime: String::from(&digits[10..20]),
+            acquiring_institution_id: String::from(&digits[20..31]),
+            forwarding_institution_id: String::from(&digits[31..42]),
+        })
+    }
+
+    /// Collect the original data elements of `msg`. DE7 and DE11 are
+    /// mandatory; absent institution IDs are encoded as zeros.
+    pub fn from_message(msg: &Message) -> Result<Self, RS8583Error> {
+        let required = |idx| {
+            msg.numeric_field(idx)?.ok_or_else(|| {
+                RS8583Error::parse_error(format!("field {}: required for original data", idx))
+            })
+        };
+        Ok(OriginalData {
+            mti: String::from_utf8_lossy(msg.mti().as_bytes()).into_owned(),
+            stan: String::from(required(STAN)?),
+            transmission_datetime: String::from(required(TRANSMISSION_DATETIME)?),
+            acquiring_institution_id: String::from(
+                msg.acquiring_institution_id()?.unwrap_or_default(),
+            ),
+            forwarding_institution_id: String::from(
+                msg.forwarding_institution_id()?.unwrap_or_default(),
+            ),
+        })
+    }
+
+    pub fn to_bytes(&self, idx: usize) -> Result<Bytes, RS8583Error> {
+        let value = format!(
+            "{}{}{}{:0>11}{:0>11}",
+            self.mti,
+            self.stan,
+            self.transmission_datetime,
+            self.acquiring_institution_id,
+            self.forwarding_institution_id
+        );
+        let field = Field::from_bytes(Bytes::from(value));
+        // Round trip through the parser to validate layout and content
+        Self::from_field(idx, &field)?;
+        Ok(field.bytes())
+    }
+}
+
+/// Security related control information (DE53), describing how to interpret
+/// the PIN block in DE52. Positions 11-16 are reserved and not decoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SecurityControl {
+    pub format_code: u8,
+    pub algorithm: u8,
+    pub pin_block_format: u8,
+    pub key_index: u8,
+    pub process_control: u8,
+}
+
+impl SecurityControl {
+    fn from_field(idx: usize, field: &Field) -> Result<Self, RS8583Error> {
+        let digits = numeric_str(idx, field)?;
+        if digits.len() != 16 {
+            return Err(RS8583Error::parse_error(format!(
+                "field {}: security control information must be 16 digits",
+                idx
+            )));
+        }
+        let part = |pos: usize| digits[pos..pos + 2].parse().unwrap();
+        Ok(SecurityControl {
+            format_code: part(0),
+            algorithm: part(2),
+            pin_block_format: part(4),
+            key_index: part(6),
+            process_control: part(8),
+        })
+    }
+}
+
+/// Track 1 data (DE45): `B<PAN>^<NAME>^<YYMM><service code><discretionary>`,
+/// optionally enclosed in the `%` and `?` sentinels.
+#[derive(Clone, PartialEq)]
+pub struct Track1 {
+    pub pan: String,
+    pub name: String,
+    pub expiry: String,
+    pub service_code: String,
+    pub discretionary: String,
+}
+
+impl Track1 {
+    fn from_field(idx: usize, field: &Field) -> Result<Self, RS8583Error> {
+        let malformed = |what: &str| {
+            RS8583Error::parse_error(format!("field {}: malformed track 1 data: {}", idx, what))
+        };
+        let data = std::str::from_utf8(field.as_slice())
+            .ok()
+            .filter(|data| data.is_ascii())
+            .ok_or_else(|| malformed("non-ASCII"))?;
+        let data = match (data.strip_prefix('%'), data.strip_suffix('?')) {
+            (Some(_), Some(_)) if data.len() > 1 => &data[1..data.len() - 1],
+            (None, None) => data,
+            _ => return Err(malformed("unbalanced sentinels")),
+        };
+        let data = data
+            .strip_prefix('B')
+            .ok_or_else(|| malformed("format code is not B"))?;
+        let mut parts = data.splitn(3, '^');
+        let (pan, name, rest) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(pan), Some(name), Some(rest)) => (pan, name, rest),
+            _ => return Err(malformed("missing separator")),
+        };
+        let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if pan.is_empty() || pan.len() > 19 || !digits(pan) {
+            return Err(malformed("invalid PAN"));
+        }
+        if rest.len() < 7 || !digits(&rest[..7]) {
+            return Err(malformed("invalid expiry or service code"));
+        }
+        Ok(Track1 {
+            pan: String::from(pan),
+            name: String::from(name),
+            expiry: String::from(&rest[..4]),
+            service_code: String::from(&rest[4..7]),
+            discretionary: String::from(&rest[7..]),
+        })
+    }
+
+    /// Track data without sentinels, with the PAN masked to its first 6 and
+    /// last 4 digits and the discretionary data blanked out.
+    pub fn masked(&self) -> String {
+        let pan = SensitivityType::MaskPAN.mask(self.pan.as_bytes());
+        format!(
+            "B{}^{}^{}{}{}",
+            String::from_utf8_lossy(&pan),
+            self.name,
+            self.expiry,
+            self.service_code,
+            "*".repeat(self.discretionary.len())
+        )
+    }
+}
+
+/// Only the masked form is printed, keeping card data out of logs.
+impl std::fmt::Debug for Track1 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Track1").field(&self.masked()).finish()
+    }
+}
+
+/// Key identifying a transaction across request and response: STAN (DE11),
+/// RRN (DE37) and transmission date and time (DE7), as raw field text.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TransactionKey {
+    pub stan: String,
+    pub rrn: String,
+    pub transmission_datetime: String,
+}
+
+/// Currency code (DE49-51), in numeric or alphabetic ISO 4217 form.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CurrencyCode<'a> {
+    Numeric(&'a str),
+    Alpha(&'a str),
+}
+
+impl<'a> CurrencyCode<'a> {
+    fn from_field(idx: usize, field: &'a Field) -> Result<Self, RS8583Error> {
+        let data = field.as_slice();
+        let code = std::str::from_utf8(data).ok().filter(|_| data.len() == 3);
+        match code {
+            Some(code) if data.iter().all(u8::is_ascii_digit) => Ok(Self::Numeric(code)),
+            Some(code) if data.iter().all(u8::is_ascii_uppercase) => Ok(Self::Alpha(code)),
+            _ => Err(RS8583Error::parse_error(format!(
+                "field {}: invalid currency code",
+                idx
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        match self {
+            Self::Numeric(code) | Self::Alpha(code) => code,
+        }
+    }
+
+    /// Check the code against the built-in ISO 4217 table.
+    #[cfg(feature = "iso4217")]
+    pub fn validate(&self) -> Result<(), RS8583Error> {
+        let known = match self {
+            Self::Numeric(code) => iso4217::numeric_to_alpha(code).is_some(),
+            Self::Alpha(code) => iso4217::alpha_to_numeric(code).is_some(),
+        };
+        if known {
+            Ok(())
+        } else {
+            Err(RS8583Error::parse_error(format!(
+                "Unknown currency code {}",
+                self.as_str()
+            )))
+        }
+    }
+}
+
+/// Conversion between field data and Rust values.
+pub trait FieldValue: Sized {
+    fn from_field(idx: usize, field: &Field) -> Result<Self, RS8583Error>;
+    fn to_field(&self, idx: usize, field_spec: &FieldSpec) -> Result<Bytes, RS8583Error>;
+}
+
+impl FieldValue for String {
+    fn from_field(idx: usize, field: &Field) -> Result<Self, RS8583Error> {
+        String::from_utf8(field.as_slice().to_vec())
+            .map_err(|_| RS8583Error::parse_error(format!("field {}: invalid UTF-8", idx)))
+    }
+
+    fn to_field(&self, _idx: usize, _field_spec: &FieldSpec) -> Result<Bytes, RS8583Error> {
+        Ok(Bytes::from(self.clone()))
+    }
+}
+
+macro_rules! impl_numeric_field_value {
+    ($($t:ty),*) => {
+        $(
+            impl FieldValue for $t {
+                fn from_field(idx: usize, field: &Field) -> Result<Self, RS8583Error> {
+                    numeric_str(idx, field)?.parse().map_err(|_| {
+                        RS8583Error::parse_error(format!("field {}: invalid numeric value", idx))
+                    })
+                }
+
+                fn to_field(&self, idx: usize, field_spec: &FieldSpec) -> Result<Bytes, RS8583Error> {
+                    match field_spec.length_type {
+                        LengthType::Fixed => {
+                            let value = format!("{:01$}", self, field_spec.length);
+                            if value.len() > field_spec.length {
+                                return Err(RS8583Error::parse_error(format!(
+                                    "field {}: value {} wider than fixed length {}",
+                                    idx, self, field_spec.length
+                                )));
+                            }
+                            Ok(value.into())
+                        }
+                        _ => Ok(self.to_string().into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_numeric_field_value!(u8, u16, u32, u64, usize);
+
+pub fn get_value<T: FieldValue>(msg: &Message, idx: usize) -> Result<T, RS8583Error> {
+    get_optional_value(msg, idx)?
+        .ok_or_else(|| RS8583Error::parse_error(format!("field {} missing", idx)))
+}
+
+pub fn get_optional_value<T: FieldValue>(
+    msg: &Message,
+    idx: usize,
+) -> Result<Option<T>, RS8583Error> {
+    msg.field(idx).map(|f| T::from_field(idx, f)).transpose()
+}
+
+pub fn set_value<T: FieldValue>(
+    msg: &mut Message,
+    idx: usize,
+    value: &T,
+) -> Result<(), RS8583Error> {
+    let field_spec = msg
+        .spec()
+        .fields
+        .get(idx)
+        .and_then(Option::as_ref)
+        .ok_or_else(|| RS8583Error::parse_error(format!("field {} not defined in spec", idx)))?;
+    let value = value.to_field(idx, field_spec)?;
+    msg.set_field(idx, value)
+}
+
+pub fn set_optional_value<T: FieldValue>(
+    msg: &mut Message,
+    idx: usize,
+    value: &Option<T>,
+) -> Result<(), RS8583Error> {
+    match value {
+        Some(value) => set_value(msg, idx, value),
+        None => {
+            msg.clear_field(idx);
+            Ok(())
+        }
+    }
+}
+
+impl<'spec> Message<'spec> {
+    fn numeric_field(&self, idx: usize) -> Result<Option<&str>, RS8583Error> {
+        self.field(idx).map(|f| numeric_str(idx, f)).transpose()
+    }
+
+    /// Description of the DE39 response code, if present and standard.
+    pub fn response_description(&self) -> Option<&'static str> {
+        self.response_description_with(|_| None)
+    }
+
+    /// Description of the DE39 response code, consulting scheme-specific
+    /// `overrides` before the standard table.
+    pub fn response_description_with<F>(&self, overrides: F) -> Option<&'static str>
+    where
+        F: Fn(&str) -> Option<&'static str>,
+    {
+        let code = std::str::from_utf8(self.field(RESPONSE_CODE)?.as_slice()).ok()?;
+        overrides(code).or_else(|| response_code_description(code))
+    }
+
+    fn currency_field(&self, idx: usize) -> Result<Option<CurrencyCode<'_>>, RS8583Error> {
+        self.field(idx)
+            .map(|f| CurrencyCode::from_field(idx, f))
+            .transpose()
+    }
+
+    /// DE49, currency code of the transaction.
+    pub fn transaction_currency(&self) -> Result<Option<CurrencyCode<'_>>, RS8583Error> {
+        self.currency_field(TRANSACTION_CURRENCY_CODE)
+    }
+
+    /// DE51, currency code of cardholder billing.
+    pub fn billing_currency(&self) -> Result<Option<CurrencyCode<'_>>, RS8583Error> {
+        self.currency_field(BILLING_CURRENCY_CODE)
+    }
+
+    /// DE48, additional private data, parsed as ID/LLVAR subelements.
+    pub fn additional_data(
+        &self,
+        codec: &Codec,
+    ) -> Result<Option<BTreeMap<usize, Bytes>>, RS8583Error> {
+        self.field(ADDITIONAL_DATA)
+            .map(|f| subfield::parse_subelements(codec, f.bytes()))
+            .transpose()
+    }
+
+    pub fn set_additional_data(
+        &mut self,
+        codec: &Codec,
+        subelements: &BTreeMap<usize, Bytes>,
+    ) -> Result<(), RS8583Error> {
+        let data = subfield::serialize_subelements(codec, subelements)?;
+        self.set_field(ADDITIONAL_DATA, data.freeze())
+    }
+
+    fn text_field(&self, idx: usize) -> Option<&str> {
+        std::str::from_utf8(self.field(idx)?.as_slice()).ok()
+    }
+
+    fn trimmed_field(&self, idx: usize) -> Option<&str> {
+        self.text_field(idx)
+            .map(|value| value.trim_end_matches(' '))
+    }
+
+    /// DE41, card acceptor terminal identification, without padding.
+    pub fn terminal_id(&self) -> Option<&str> {
+        self.trimmed_field(CARD_ACCEPTOR_TERMINAL_ID)
+    }
+
+    pub fn set_terminal_id(&mut self, value: &str) -> Result<(), RS8583Error> {
+        set_space_padded(self, CARD_ACCEPTOR_TERMINAL_ID, value, 8)
+    }
+
+    /// DE42, card acceptor identification code, without padding.
+    pub fn card_acceptor_id(&self) -> Option<&str> {
+        self.trimmed_field(CARD_ACCEPTOR_ID)
+    }
+
+    pub fn set_card_acceptor_id(&mut self, value: &str) -> Result<(), RS8583Error> {
+        set_space_padded(self, CARD_ACCEPTOR_ID, value, 15)
+    }
+
+    /// Matching key built from DE11, DE37 and DE7. `None` unless all three
+    /// are present; partial keys are not built, as they would match
+    /// unrelated transactions.
+    pub fn transaction_key(&self) -> Option<TransactionKey> {
+        Some(TransactionKey {
+            stan: String::from(self.text_field(STAN)?),
+            rrn: String::from(self.text_field(RETRIEVAL_REFERENCE_NUMBER)?),
+            transmission_datetime: String::from(self.text_field(TRANSMISSION_DATETIME)?),
+        })
+    }
+
+    /// DE37, retrieval reference number.
+    pub fn rrn(&self) -> Option<&str> {
+        self.text_field(RETRIEVAL_REFERENCE_NUMBER)
+    }
+
+    pub fn set_rrn(&mut self, rrn: &str) -> Result<(), RS8583Error> {
+        set_fixed_alphanumeric(self, RETRIEVAL_REFERENCE_NUMBER, rrn, 12)
+    }
+
+    /// DE38, authorization identification response.
+    pub fn auth_id(&self) -> Option<&str> {
+        self.text_field(AUTHORIZATION_ID_RESPONSE)
+    }
+
+    pub fn set_auth_id(&mut self, auth_id: &str) -> Result<(), RS8583Error> {
+        set_fixed_alphanumeric(self, AUTHORIZATION_ID_RESPONSE, auth_id, 6)
+    }
+
+    fn amount_field(&self, idx: usize) -> Result<Option<u64>, RS8583Error> {
+        self.field(idx)
+            .map(|f| {
+                let digits = numeric_str(idx, f)?;
+                if digits.len() != 12 {
+                    return Err(RS8583Error::parse_error(format!(
+                        "field {}: amount must be 12 digits",
+                        idx
+                    )));
+                }
+                Ok(digits.parse().unwrap())
+            })
+            .transpose()
+    }
+
+    /// DE4, transaction amount in minor units.
+    pub fn amount_transaction(&self) -> Result<Option<u64>, RS8583Error> {
+        self.amount_field(AMOUNT_TRANSACTION)
+    }
+
+    /// DE5, settlement amount in minor units.
+    pub fn amount_settlement(&self) -> Result<Option<u64>, RS8583Error> {
+        self.amount_field(AMOUNT_SETTLEMENT)
+    }
+
+    /// DE6, cardholder billing amount in minor units.
+    pub fn amount_cardholder_billing(&self) -> Result<Option<u64>, RS8583Error> {
+        self.amount_field(AMOUNT_CARDHOLDER_BILLING)
+    }
+
+    /// DE9, settlement conversion rate.
+    pub fn conversion_rate_settlement(&self) -> Result<Option<ConversionRate>, RS8583Error> {
+        self.field(CONVERSION_RATE_SETTLEMENT)
+            .map(|f| ConversionRate::from_field(CONVERSION_RATE_SETTLEMENT, f))
+            .transpose()
+    }
+
+    /// DE10, cardholder billing conversion rate.
+    pub fn conversion_rate_cardholder_billing(
+        &self,
+    ) -> Result<Option<ConversionRate>, RS8583Error> {
+        self.field(CONVERSION_RATE_CARDHOLDER_BILLING)
+            .map(|f| ConversionRate::from_field(CONVERSION_RATE_CARDHOLDER_BILLING, f))
+            .transpose()
+    }
+
+    /// Card scheme of the DE2 PAN, according to the default BIN table.
+    pub fn card_scheme(&self) -> Option<CardScheme> {
+        self.card_scheme_with(&BinTable::default())
+    }
+
+    pub fn card_scheme_with(&self, table: &BinTable) -> Option<CardScheme> {
+        table.classify(self.text_field(PAN)?)
+    }
+
+    /// DE90, original data elements of a reversal.
+    pub fn original_data(&self) -> Result<Option<OriginalData>, RS8583Error> {
+        self.field(ORIGINAL_DATA_ELEMENTS)
+            .map(|f| OriginalData::from_field(ORIGINAL_DATA_ELEMENTS, f))
+            .transpose()
+    }
+
+    pub fn set_original_data(&mut self, data: &OriginalData) -> Result<(), RS8583Error> {
+        let value = data.to_bytes(ORIGINAL_DATA_ELEMENTS)?;
+        self.set_field(ORIGINAL_DATA_ELEMENTS, value)
+    }
+
+    /// Reversal of this message: same fields, reversal MTI, and DE90
+    /// referring back to this message.
+    pub fn build_reversal_from_original(&self) -> Result<Message<'spec>, RS8583Error> {
+        let original = OriginalData::from_message(self)?;
+        let mut reversal = self.clone();
+        reversal.set_mti(self.mti().to_reversal()?);
+        reversal.set_original_data(&original)?;
+        Ok(reversal)
+    }
+
+    /// Scheme-specific POS data: DE60 for Visa, DE61 for Mastercard.
+    pub fn pos_data(&self, scheme: CardScheme) -> Result<Option<PosData<'_>>, RS8583Error> {
+        let (idx, _) = pos_data_layout(scheme)?;
+        self.field(idx)
+            .map(|f| parse_pos_data(scheme, f.as_slice()))
+            .transpose()
+    }
+
+    /// DE53, security related control information.
+    pub fn security_control(&self) -> Result<Option<SecurityControl>, RS8583Error> {
+        self.field(SECURITY_CONTROL)
+            .map(|f| SecurityControl::from_field(SECURITY_CONTROL, f))
+            .transpose()
+    }
+
+    /// DE45, track 1 data.
+    pub fn track1(&self) -> Result<Option<Track1>, RS8583Error> {
+        self.field(TRACK_1_DATA)
+            .map(|f| Track1::from_field(TRACK_1_DATA, f))
+            .transpose()
+    }
+
+    /// DE52 PIN block together with the DE53 information needed to interpret
+    /// it. PIN data without DE53 is an error.
+    pub fn pin_block(&self) -> Result<Option<(&[u8], SecurityControl)>, RS8583Error> {
+        let pin = match self.field(PIN_DATA) {
+            Some(pin) => pin.as_slice(),
+            None => return Ok(None),
+        };
+        match self.security_control()? {
+            Some(control) => Ok(Some((pin, control))),
+            None => Err(RS8583Error::parse_error(format!(
+                "field {}: PIN data without security control information",
+                PIN_DATA
+            ))),
+        }
+    }
+
+    /// DE32, acquiring institution identification code.
+    pub fn acquiring_institution_id(&self) -> Result<Option<&str>, RS8583Error> {
+        self.numeric_field(ACQUIRING_INSTITUTION_ID)
+    }
+
+    pub fn set_acquiring_institution_id<T>(&mut self, value: T) -> Result<(), RS8583Error>
+    where
+        T: Into<Bytes>,
+    {
+        set_numeric(self, ACQUIRING_INSTITUTION_ID, value)
+    }
+
+    /// DE33, forwarding institution identification code.
+    pub fn forwarding_institution_id(&self) -> Result<Option<&str>, RS8583Error> {
+        self.numeric_field(FORWARDING_INSTITUTION_ID)
+    }
+
+    pub fn set_forwarding_institution_id<T>(&mut self, value: T) -> Result<(), RS8583Error>
+    where
+        T: Into<Bytes>,
+    {
+        set_numeric(self, FORWARDING_INSTITUTION_ID, value)
+    }
+}
+
+/// Date and time accessors. ISO 8583 date/time fields carry neither a year
+/// nor a timezone, so both have to be supplied by the caller: DE7 is usually
+/// GMT, while DE12/DE13 are in the acquirer's local time.
+#[cfg(feature = "chrono")]
+impl<'spec> Message<'spec> {
+    /// DE7, transmission date and time, interpreted as UTC.
+    pub fn transmission_datetime(&self, year: i32) -> Result<Option<DateTime<Utc>>, RS8583Error> {
+        self.transmission_datetime_in(&Utc, year)
+    }
+
+    /// DE7, transmission date and time, interpreted in `tz`.
+    pub fn transmission_datetime_in<Tz: TimeZone>(
+        &self,
+        tz: &Tz,
+        year: i32,
+    ) -> Result<Option<DateTime<Tz>>, RS8583Error> {
+        self.numeric_field(TRANSMISSION_DATETIME)?
+            .map(|digits| parse_datetime(TRANSMISSION_DATETIME, digits, tz, year))
+            .transpose()
+    }
+
+    /// DE13 and DE12, local transaction date and time, interpreted in `tz`.
+    /// `None` unless both fields are present.
+    pub fn local_datetime_in<Tz: TimeZone>(
+        &self,
+        tz: &Tz,
+        year: i32,
+    ) -> Result<Option<DateTime<Tz>>, RS8583Error> {
+        let (date, time) = match (
+            self.numeric_field(LOCAL_DATE)?,
+            self.numeric_field(LOCAL_TIME)?,
+        ) {
+            (Some(date), Some(time)) if date.len() == 4 && time.len() == 6 => (date, time),
+            (Some(_), Some(_)) => {
+                return Err(RS8583Error::parse_error(format!(
+                    "field {}: invalid date/time",
+                    LOCAL_DATE
+                )))
+            }
+            _ => return Ok(None),
+        };
+        parse_datetime(LOCAL_DATE, &format!("{}{}", date, time), tz, year).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::*;
+
+    fn institution_spec() -> MessageSpec {
+        let mut fields: Vec<Option<FieldSpec>> = (0..128).map(|_| None).collect();
+        fields[ACQUIRING_INSTITUTION_ID] = Some(FieldSpec {
+            name: String::from("ACQUIRING INSTITUTION ID"),
+            field_type: FieldType::N,
+            length_type: LengthType::LLVar,
+            sensitivity: SensitivityType::Normal,
+            length: 11,
+            ..FieldSpec::default()
+        });
+        fields[FORWARDING_INSTITUTION_ID] = Some(FieldSpec {
+            name: String::from("FORWARDING INSTITUTION ID"),
+            field_type: FieldType::N,
+            length_type: LengthType::LLVar,
+            sensitivity: SensitivityType::Normal,
+            length: 11,
+            ..FieldSpec::default()
+        });
+        MessageSpec {
+            fields,
+            ..MessageSpec::default()
+        }
+    }
+
+    #[test]
+    fn de32_round_trip() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = institution_spec();
+        let raw = b"0200\x00\x00\x00\x80\x00\x00\x00\x0006123456".to_vec();
+        let orig_raw = raw.clone();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let id = msg.acquiring_institution_id()?.unwrap();
+        assert_eq!(id, "123456");
+        assert_eq!(id.len(), 6);
+        assert_eq!(msg.forwarding_institution_id()?, None);
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        msg.set_forwarding_institution_id("654321")?;
+        assert_eq!(msg.forwarding_institution_id()?, Some("654321"));
+        assert_eq!(
+            msg.set_acquiring_institution_id("12AB56"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 31: non-numeric value"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn response_codes() -> Result<(), RS8583Error> {
+        assert_eq!(
+            response_code_description("00"),
+            Some("Approved or completed successfully")
+        );
+        assert_eq!(response_code_description("05"), Some("Do not honor"));
+        assert_eq!(
+            response_code_description("51"),
+            Some("Not sufficient funds")
+        );
+        assert_eq!(response_code_description("N7"), None);
+
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[RESPONSE_CODE] = Some(FieldSpec {
+            name: String::from("RESPONSE CODE"),
+            field_type: FieldType::AN,
+            length: 2,
+            ..FieldSpec::default()
+        });
+        let raw = b"0210\x00\x00\x00\x00\x40\x00\x00\x0051".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.response_description(), Some("Not sufficient funds"));
+
+        msg.set_field(RESPONSE_CODE, "N7")?;
+        assert_eq!(msg.response_description(), None);
+        let overrides = |code: &str| match code {
+            "N7" => Some("Decline for CVV2 failure"),
+            _ => None,
+        };
+        assert_eq!(
+            msg.response_description_with(overrides),
+            Some("Decline for CVV2 failure")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "iso4217")]
+    fn currency_codes() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        for idx in &[TRANSACTION_CURRENCY_CODE, BILLING_CURRENCY_CODE] {
+            spec.fields[*idx] = Some(FieldSpec {
+                name: format!("CURRENCY CODE {}", idx),
+                field_type: FieldType::AN,
+                length: 3,
+                ..FieldSpec::default()
+            });
+        }
+        let raw = b"0200\x00\x00\x00\x00\x00\x00\x05\x00840999".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let currency = msg.transaction_currency()?.unwrap();
+        assert_eq!(currency, CurrencyCode::Numeric("840"));
+        assert_eq!(currency.validate(), Ok(()));
+
+        let currency = msg.billing_currency()?.unwrap();
+        assert_eq!(currency, CurrencyCode::Numeric("999"));
+        assert_eq!(
+            currency.validate(),
+            Err(RS8583Error::ParseError {
+                error: String::from("Unknown currency code 999"),
+            })
+        );
+
+        msg.set_field(BILLING_CURRENCY_CODE, "EUR")?;
+        let currency = msg.billing_currency()?.unwrap();
+        assert_eq!(currency, CurrencyCode::Alpha("EUR"));
+        assert_eq!(currency.validate(), Ok(()));
+
+        msg.set_field(BILLING_CURRENCY_CODE, "eu1")?;
+        assert_eq!(
+            msg.billing_currency(),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 50: invalid currency code"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn de48_subelements() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[ADDITIONAL_DATA] = Some(FieldSpec {
+            name: String::from("ADDITIONAL DATA"),
+            length_type: LengthType::LLLVar,
+            length: 999,
+            ..FieldSpec::default()
+        });
+        let raw = b"0100\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.additional_data(&codec)?, None);
+
+        let mut subelements = BTreeMap::new();
+        subelements.insert(2, Bytes::from("XYZ"));
+        subelements.insert(1, Bytes::from("12"));
+        msg.set_additional_data(&codec, &subelements)?;
+        assert_eq!(
+            msg.field(ADDITIONAL_DATA).unwrap().as_slice(),
+            b"0102120203XYZ"
+        );
+        assert_eq!(msg.additional_data(&codec)?, Some(subelements));
+
+        Ok(())
+    }
+
+    #[test]
+    fn de37_rrn() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[RETRIEVAL_REFERENCE_NUMBER] = Some(FieldSpec {
+            name: String::from("RETRIEVAL REFERENCE NUMBER"),
+            field_type: FieldType::AN,
+            length: 12,
+            ..FieldSpec::default()
+        });
+        let raw = b"0200\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.rrn(), None);
+
+        msg.set_rrn("123456ABCDEF")?;
+        assert_eq!(msg.rrn(), Some("123456ABCDEF"));
+        assert_eq!(
+            msg.set_rrn("12345"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 36: expected exactly 12 alphanumeric characters"),
+            })
+        );
+        assert_eq!(msg.rrn(), Some("123456ABCDEF"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn de38_auth_id() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[AUTHORIZATION_ID_RESPONSE] = Some(FieldSpec {
+            name: String::from("AUTHORIZATION ID RESPONSE"),
+            field_type: FieldType::AN,
+            length: 6,
+            ..FieldSpec::default()
+        });
+        let raw = b"0210\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.auth_id(), None);
+
+        msg.set_auth_id("A1B2C3")?;
+        assert_eq!(msg.auth_id(), Some("A1B2C3"));
+        for bad in &["A1B2C", "A1B2C3D"] {
+            assert_eq!(
+                msg.set_auth_id(bad),
+                Err(RS8583Error::ParseError {
+                    error: String::from("field 37: expected exactly 6 alphanumeric characters"),
+                })
+            );
+        }
+        assert_eq!(msg.auth_id(), Some("A1B2C3"));
+
+        let serialized = msg.serialize(&codec)?;
+        let parsed = Message::from_bytes(&spec, &codec, serialized.freeze())?;
+        assert_eq!(parsed.auth_id(), Some("A1B2C3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn amounts_and_rates() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        for (idx, length) in &[
+            (AMOUNT_TRANSACTION, 12),
+            (AMOUNT_SETTLEMENT, 12),
+            (AMOUNT_CARDHOLDER_BILLING, 12),
+            (CONVERSION_RATE_SETTLEMENT, 8),
+            (CONVERSION_RATE_CARDHOLDER_BILLING, 8),
+        ] {
+            spec.fields[*idx] = Some(FieldSpec {
+                name: format!("FIELD {}", idx),
+                field_type: FieldType::N,
+                length: *length,
+                ..FieldSpec::default()
+            });
+        }
+        let raw = b"0200\x38\x03\x00\x00\x00\x00\x00\x00\
+            000000001000000000000900000000001150\
+            6100000079972522"
+            .to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.amount_transaction()?, Some(1000));
+        assert_eq!(msg.amount_settlement()?, Some(900));
+        assert_eq!(msg.amount_cardholder_billing()?, Some(1150));
+
+        let rate = msg.conversion_rate_settlement()?.unwrap();
+        assert_eq!(
+            rate,
+            ConversionRate {
+                decimals: 6,
+                mantissa: 1000000,
+            }
+        );
+        assert_eq!(rate.to_string(), "1.000000");
+        assert!((rate.to_f64() - 1.0).abs() < 1e-9);
+
+        let rate = msg.conversion_rate_cardholder_billing()?.unwrap();
+        assert_eq!(rate.to_string(), "0.9972522");
+        assert!((rate.to_f64() - 0.9972522).abs() < 1e-9);
+
+        msg.set_field(CONVERSION_RATE_SETTLEMENT, "6100000X")?;
+        assert_eq!(
+            msg.conversion_rate_settlement(),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 8: non-numeric value"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn datetime_fields() -> Result<(), RS8583Error> {
+        use chrono::FixedOffset;
+
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        let numeric = |name: &str, length| FieldSpec {
+            name: String::from(name),
+            field_type: FieldType::N,
+            length,
+            ..FieldSpec::default()
+        };
+        spec.fields[TRANSMISSION_DATETIME] = Some(numeric("TRANSMISSION DATE AND TIME", 10));
+        spec.fields[LOCAL_TIME] = Some(numeric("LOCAL TIME", 6));
+        spec.fields[LOCAL_DATE] = Some(numeric("LOCAL DATE", 4));
+        let raw = b"0200\x40\x08\x00\x00\x00\x00\x00\x001014153000153000".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.local_datetime_in(&Utc, 2026)?, None);
+
+        let utc = Utc.with_ymd_and_hms(2026, 10, 14, 15, 30, 0).unwrap();
+        assert_eq!(msg.transmission_datetime(2026)?, Some(utc));
+
+        let tz = FixedOffset::east_opt(3 * 3600).unwrap();
+        let local = msg.transmission_datetime_in(&tz, 2026)?.unwrap();
+        assert_eq!(local.offset(), &tz);
+        assert_eq!(
+            local,
+            Utc.with_ymd_and_hms(2026, 10, 14, 12, 30, 0).unwrap()
+        );
+
+        msg.set_field(LOCAL_DATE, "1014")?;
+        assert_eq!(msg.local_datetime_in(&tz, 2026)?, Some(local));
+
+        msg.set_field(TRANSMISSION_DATETIME, "1332153000")?;
+        assert_eq!(
+            msg.transmission_datetime(2026),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 6: invalid date/time"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn de53_security_control() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[PIN_DATA] = Some(FieldSpec {
+            name: String::from("PIN DATA"),
+            field_type: FieldType::B,
+            length: 8,
+            ..FieldSpec::default()
+        });
+        spec.fields[SECURITY_CONTROL] = Some(FieldSpec {
+            name: String::from("SECURITY RELATED CONTROL INFORMATION"),
+            field_type: FieldType::N,
+            length: 16,
+            ..FieldSpec::default()
+        });
+        let raw =
+            b"0200\x00\x00\x00\x00\x00\x00\x18\x00\x01\x23\x45\x67\x89\xab\xcd\xef2001010300000000"
+                .to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let control = SecurityControl {
+            format_code: 20,
+            algorithm: 1,
+            pin_block_format: 1,
+            key_index: 3,
+            process_control: 0,
+        };
+        assert_eq!(msg.security_control()?, Some(control));
+        assert_eq!(
+            msg.pin_block()?,
+            Some((&b"\x01\x23\x45\x67\x89\xab\xcd\xef"[..], control))
+        );
+
+        msg.set_field(SECURITY_CONTROL, "20010103000000AB")?;
+        assert_eq!(
+            msg.security_control(),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 52: non-numeric value"),
+            })
+        );
+        msg.clear_field(SECURITY_CONTROL);
+        assert_eq!(
+            msg.pin_block(),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 51: PIN data without security control information"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn pos_data_per_scheme() -> Result<(), RS8583Error> {
+        let data = b"250000000510";
+        let visa = parse_pos_data(CardScheme::Visa, data)?;
+        assert_eq!(visa.subfields.len(), 10);
+        assert_eq!(visa.get("terminal_entry_capability"), Some("5"));
+        assert_eq!(visa.get("ecommerce_indicator"), Some("05"));
+        let mastercard = parse_pos_data(CardScheme::Mastercard, data)?;
+        assert_eq!(mastercard.subfields.len(), 12);
+        assert_eq!(mastercard.get("reserved_2"), Some("5"));
+        assert_eq!(mastercard.get("authorization_life_cycle"), Some("0"));
+        assert_eq!(mastercard.get("postal_code"), None);
+
+        assert_eq!(
+            parse_pos_data(CardScheme::Visa, b"2500000005101").err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Invalid POS data: 1 bytes past the end of the layout"),
+            })
+        );
+
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[MASTERCARD_POS_DATA] = Some(FieldSpec {
+            name: String::from("POS DATA"),
+            field_type: FieldType::ANS,
+            length_type: LengthType::LLLVar,
+            length: 26,
+            ..FieldSpec::default()
+        });
+        let raw = b"0100\x00\x00\x00\x00\x00\x00\x00\x100131025500001000".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        let pos = msg.pos_data(CardScheme::Mastercard)?.unwrap();
+        assert_eq!(pos.get("card_presence"), Some("5"));
+        assert_eq!(pos.get("authorization_life_cycle"), Some("00"));
+        assert_eq!(msg.pos_data(CardScheme::Visa)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn card_scheme_from_bin() -> Result<(), RS8583Error> {
+        let table = BinTable::default();
+        assert_eq!(table.classify("4111111111111111"), Some(CardScheme::Visa));
+        assert_eq!(table.classify("341111111111111"), Some(CardScheme::Amex));
+        assert_eq!(table.classify("371449635398431"), Some(CardScheme::Amex));
+        assert_eq!(
+            table.classify("5500000000000004"),
+            Some(CardScheme::Mastercard)
+        );
+        assert_eq!(
+            table.classify("2720990000000000"),
+            Some(CardScheme::Mastercard)
+        );
+        assert_eq!(table.classify("2721000000000000"), None);
+        assert_eq!(table.classify("3"), None);
+
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[PAN] = Some(FieldSpec {
+            name: String::from("PAN"),
+            field_type: FieldType::N,
+            length_type: LengthType::LLVar,
+            length: 19,
+            ..FieldSpec::default()
+        });
+        let raw = b"0100\x02\x00\x00\x00\x00\x00\x00\x00164111111111111111".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.card_scheme(), Some(CardScheme::Visa));
+
+        msg.set_field(PAN, "378282246310005")?;
+        assert_eq!(msg.card_scheme(), Some(CardScheme::Amex));
+
+        let mut table = BinTable::default();
+        table.ranges.insert(
+            0,
+            BinRange {
+                low: String::from("3782"),
+                high: String::from("3782"),
+                scheme: CardScheme::Discover,
+            },
+        );
+        assert_eq!(msg.card_scheme_with(&table), Some(CardScheme::Discover));
+
+        msg.clear_field(PAN);
+        assert_eq!(msg.card_scheme(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn de90_original_data() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        let numeric = |name: &str, length| FieldSpec {
+            name: String::from(name),
+            field_type: FieldType::N,
+            length,
+            ..FieldSpec::default()
+        };
+        spec.fields[TRANSMISSION_DATETIME] = Some(numeric("TRANSMISSION DATE AND TIME", 10));
+        spec.fields[STAN] = Some(numeric("STAN", 6));
+        spec.fields[ORIGINAL_DATA_ELEMENTS] = Some(numeric("ORIGINAL DATA ELEMENTS", 42));
+        let raw = b"0200\x40\x04\x00\x80\x00\x00\x00\x001014153000000123061234560".to_vec();
+        let original = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let reversal = original.build_reversal_from_original()?;
+        assert_eq!(reversal.mti().as_bytes(), b"0400");
+        assert_eq!(
+            reversal.field(ORIGINAL_DATA_ELEMENTS).unwrap().as_slice(),
+            &b"020000012310141530000000012345600000000000"[..]
+        );
+
+        let serialized = reversal.serialize(&codec)?;
+        let parsed = Message::from_bytes(&spec, &codec, serialized.freeze())?;
+        assert_eq!(
+            parsed.original_data()?,
+            Some(OriginalData {
+                mti: String::from("0200"),
+                stan: String::from("000123"),
+                transmission_datetime: String::from("1014153000"),
+                acquiring_institution_id: String::from("00000123456"),
+                forwarding_institution_id: String::from("00000000000"),
+            })
+        );
+
+        let mut short = reversal.clone();
+        short.set_field_raw(ORIGINAL_DATA_ELEMENTS, "0200000123")?;
+        assert_eq!(
+            short.original_data(),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 89: original data elements must be 42 digits"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn de41_de42_padding() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        let ans = |name: &str, length| FieldSpec {
+            name: String::from(name),
+            field_type: FieldType::ANS,
+            length,
+            ..FieldSpec::default()
+        };
+        spec.fields[CARD_ACCEPTOR_TERMINAL_ID] = Some(ans("CARD ACCEPTOR TERMINAL ID", 8));
+        spec.fields[CARD_ACCEPTOR_ID] = Some(ans("CARD ACCEPTOR ID", 15));
+        let raw = b"0200\x00\x00\x00\x00\x00\x03\x00\x00TERM01  MERCHANT 42    ".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.terminal_id(), Some("TERM01"));
+        assert_eq!(msg.card_acceptor_id(), Some("MERCHANT 42"));
+
+        msg.set_terminal_id("T1")?;
+        assert_eq!(
+            msg.field(CARD_ACCEPTOR_TERMINAL_ID).unwrap().as_slice(),
+            b"T1      "
+        );
+        assert_eq!(msg.terminal_id(), Some("T1"));
+        assert_eq!(
+            msg.set_card_acceptor_id("MERCHANT 4200000"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 41: value longer than 15 characters"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn de45_track1() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = institution_spec();
+        spec.fields[TRACK_1_DATA] = Some(FieldSpec {
+            name: String::from("TRACK 1 DATA"),
+            length_type: LengthType::LLVar,
+            length: 76,
+            ..FieldSpec::default()
+        });
+        let track = "%B4111111111111111^DOE/JOHN^2512101123456789?";
+        let mut raw = b"0200\x00\x00\x00\x00\x00\x10\x00\x00".to_vec();
+        raw.extend_from_slice(format!("{:02}{}", track.len(), track).as_bytes());
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let track1 = msg.track1()?.unwrap();
+        assert_eq!(track1.pan, "4111111111111111");
+        assert_eq!(track1.name, "DOE/JOHN");
+        assert_eq!(track1.expiry, "2512");
+        assert_eq!(track1.service_code, "101");
+        assert_eq!(track1.discretionary, "123456789");
+        assert_eq!(
+            track1.masked(),
+            "B411111******1111^DOE/JOHN^2512101*********"
+        );
+        assert!(!format!("{:?}", track1).contains("4111111111111111"));
+        assert_eq!(
+            msg.masked_field(TRACK_1_DATA).unwrap().as_ref(),
+            &b"*".repeat(track.len())[..]
+        );
+
+        msg.set_field(TRACK_1_DATA, &track[1..track.len() - 1])?;
+        assert_eq!(msg.track1()?.unwrap().pan, "4111111111111111");
+
+        msg.set_field(TRACK_1_DATA, &track[..track.len() - 1])?;
+        assert_eq!(
+            msg.track1().err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("field 44: malformed track 1 data: unbalanced sentinels"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transaction_key_matching() -> Result<(), RS8583Error> {
+        use crate::msg::MTI;
+        use std::collections::HashMap;
+
+        let mut spec = institution_spec();
+        let field = |name: &str, length| FieldSpec {
+            name: String::from(name),
+            length,
+            ..FieldSpec::default()
+        };
+        spec.fields[TRANSMISSION_DATETIME] = Some(field("TRANSMISSION DATE AND TIME", 10));
+        spec.fields[STAN] = Some(field("STAN", 6));
+        spec.fields[RETRIEVAL_REFERENCE_NUMBER] = Some(field("RRN", 12));
+        let build = |mti: &str| -> Result<Message, RS8583Error> {
+            let mut msg = Message::with_mti(&spec, mti.parse::<MTI>()?);
+            msg.set_field(TRANSMISSION_DATETIME, "1014153000")?;
+            msg.set_field(STAN, "000123")?;
+            msg.set_rrn("428715000123")?;
+            Ok(msg)
+        };
+
+        let request = build("0200")?;
+        let mut response = build("0210")?;
+        let mut in_flight = HashMap::new();
+        in_flight.insert(request.transaction_key().unwrap(), "pending");
+        assert_eq!(response.transaction_key(), request.transaction_key());
+        assert_eq!(
+            in_flight.get(&response.transaction_key().unwrap()),
+            Some(&"pending")
+        );
+
+        response.set_field(STAN, "000124")?;
+        assert_ne!(response.transaction_key(), request.transaction_key());
+        response.clear_field(RETRIEVAL_REFERENCE_NUMBER);
+        assert_eq!(response.transaction_key(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_value_fixed_overflow() -> Result<(), RS8583Error> {
+        let mut spec = institution_spec();
+        spec.fields[STAN] = Some(FieldSpec {
+            name: String::from("STAN"),
+            field_type: FieldType::N,
+            length: 6,
+            ..FieldSpec::default()
+        });
+        let mut msg = Message::new(&spec);
+        set_value(&mut msg, STAN, &42u32)?;
+        assert_eq!(msg.field(STAN).unwrap().as_slice(), b"000042");
+        assert_eq!(
+            set_value(&mut msg, STAN, &1234567u32),
+            Err(RS8583Error::parse_error(
+                "field 10: value 1234567 wider than fixed length 6"
+            ))
+        );
+        assert_eq!(msg.field(STAN).unwrap().as_slice(), b"000042");
+
+        Ok(())
+    }
+}
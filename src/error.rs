@@ -1,16 +1,89 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
 use thiserror::Error;
 
+/// With the `serde` feature, errors serialize as objects tagged by `kind`,
+/// e.g. `{"kind":"ParseError","error":"Truncated field"}`.
 #[derive(Error, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(tag = "kind"))]
 pub enum RS8583Error {
     #[error("ISO8583 parse error: {error}")]
     ParseError { error: String },
+    #[error("ISO8583 configuration error: {error}")]
+    ConfigError { error: String },
+    /// Failure while processing field `field` (an index, not a DE number).
+    #[error("field {field} ({name}): {source}")]
+    FieldParseError {
+        field: usize,
+        name: String,
+        source: Box<RS8583Error>,
+    },
 }
 
-// TODO: FieldParseError with field refs
 impl RS8583Error {
     pub fn parse_error<T: ToString>(error: T) -> Self {
         Self::ParseError {
             error: error.to_string(),
         }
     }
+
+    pub fn config_error<T: ToString>(error: T) -> Self {
+        Self::ConfigError {
+            error: error.to_string(),
+        }
+    }
+
+    pub fn field_parse_error<T: ToString>(field: usize, name: T, source: RS8583Error) -> Self {
+        Self::FieldParseError {
+            field,
+            name: name.to_string(),
+            source: Box::new(source),
+        }
+    }
+
+    /// Name field `field` in a parse error raised outside of a
+    /// `FieldParseError`, as in "field 2: length 3 above max 2".
+    pub(crate) fn in_field(self, field: usize) -> Self {
+        match self {
+            Self::ParseError { error } => Self::ParseError {
+                error: format!("field {}: {}", field, error),
+            },
+            e => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_parse_error_display() {
+        let err = RS8583Error::field_parse_error(
+            47,
+            "ADDITIONAL DATA",
+            RS8583Error::parse_error("Truncated field"),
+        );
+        assert_eq!(
+            err.to_string(),
+            "field 47 (ADDITIONAL DATA): ISO8583 parse error: Truncated field"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn field_parse_error_serialize() {
+        let err = RS8583Error::field_parse_error(
+            47,
+            "ADDITIONAL DATA",
+            RS8583Error::parse_error("Truncated field"),
+        );
+        assert_eq!(
+            serde_json::to_string(&err).unwrap(),
+            concat!(
+                r#"{"kind":"FieldParseError","field":47,"name":"ADDITIONAL DATA","#,
+                r#""source":{"kind":"ParseError","error":"Truncated field"}}"#
+            )
+        );
+    }
 }
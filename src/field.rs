@@ -1,13 +1,41 @@
+use std::convert::TryFrom;
+
 use bytes::Bytes;
 
+use crate::codec::Codec;
+use crate::error::RS8583Error;
+use crate::spec::{FieldSpec, SensitivityType};
+use crate::subfield::SubfieldSpec;
+use crate::tlv::{self, Tag};
+
+/// Shortest run of digits treated as a card number by
+/// [`Field::as_private_data`].
+const PAN_LIKE_DIGITS: usize = 12;
+
+/// Field value. Its `Debug` output shows the raw bytes, card numbers
+/// included; log fields through [`MaskedField`] instead.
 #[derive(Clone, Debug)]
 pub struct Field {
     data: Bytes,
+    raw: Option<Bytes>,
 }
 
 impl Field {
     pub fn from_bytes(data: Bytes) -> Self {
-        Field { data }
+        Field { data, raw: None }
+    }
+
+    /// Field carrying the exact wire bytes it was parsed from, including any
+    /// length prefix and filler.
+    pub fn with_raw(data: Bytes, raw: Bytes) -> Self {
+        Field {
+            data,
+            raw: Some(raw),
+        }
+    }
+
+    pub fn raw(&self) -> Option<&[u8]> {
+        self.raw.as_ref().map(|raw| raw.as_ref())
     }
 
     pub fn len(&self) -> usize {
@@ -21,4 +49,243 @@ impl Field {
     pub fn as_slice(&self) -> &[u8] {
         self.data.as_ref()
     }
+
+    /// Field data as text. Fails if the data is not valid UTF-8.
+    pub fn as_str(&self) -> Result<&str, RS8583Error> {
+        std::str::from_utf8(self.as_slice())
+            .map_err(|e| RS8583Error::parse_error(format!("Field is not valid text: {}", e)))
+    }
+
+    /// Field data as an unsigned number of ASCII digits. Leading zeros are
+    /// allowed; an empty field is an error.
+    pub fn as_u64(&self) -> Result<u64, RS8583Error> {
+        parse_digits(self.as_slice())
+    }
+
+    /// Field data as a signed number of ASCII digits, optionally preceded by
+    /// `+`/`-` or the ISO `C`/`D` (credit/debit) amount sign.
+    pub fn as_i64(&self) -> Result<i64, RS8583Error> {
+        let data = self.as_slice();
+        let (negative, digits) = match data.first() {
+            Some(b'-') | Some(b'D') => (true, &data[1..]),
+            Some(b'+') | Some(b'C') => (false, &data[1..]),
+            _ => (false, data),
+        };
+        let value = parse_digits(digits)?;
+        let out_of_range = || {
+            RS8583Error::parse_error(format!(
+                "Number out of range: \"{}\"",
+                String::from_utf8_lossy(data)
+            ))
+        };
+        if negative {
+            0i64.checked_sub_unsigned(value).ok_or_else(out_of_range)
+        } else {
+            i64::try_from(value).map_err(|_| out_of_range())
+        }
+    }
+
+    /// Split the value into subfields according to `layout`, typically the
+    /// `subfields` of its field spec.
+    pub fn subfields(
+        &self,
+        codec: &Codec,
+        layout: &SubfieldSpec,
+    ) -> Result<Vec<Field>, RS8583Error> {
+        layout.parse(codec, self.bytes())
+    }
+
+    /// Value parsed as a sequence of BER-TLV objects, as in DE55. Values
+    /// share the field buffer.
+    pub fn as_tlv(&self) -> Result<Vec<(Tag, Bytes)>, RS8583Error> {
+        tlv::parse_tlv_bytes(self.bytes())
+    }
+
+    /// Free-form private data (such as DE48, DE62 or DE63 without a
+    /// subfield spec) as a display string. Bytes outside printable ASCII are
+    /// shown as `\xNN` escapes. With `mask`, runs of 12 or more digits are
+    /// assumed to be card numbers and masked like a PAN.
+    pub fn as_private_data(&self, mask: bool) -> String {
+        let mut out = String::with_capacity(self.data.len());
+        let mut rest = self.as_slice();
+        while !rest.is_empty() {
+            let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+            if digits > 0 {
+                let (run, tail) = rest.split_at(digits);
+                let run = if mask && digits >= PAN_LIKE_DIGITS {
+                    SensitivityType::MaskPAN.mask(run)
+                } else {
+                    run.into()
+                };
+                out.push_str(&String::from_utf8_lossy(&run));
+                rest = tail;
+                continue;
+            }
+            match rest[0] {
+                b if b == b' ' || b.is_ascii_graphic() => out.push(b as char),
+                b => out.push_str(&format!("\\x{:02x}", b)),
+            }
+            rest = &rest[1..];
+        }
+        out
+    }
+
+    /// Field data as `Bytes`, sharing the underlying buffer (no copy).
+    pub fn bytes(&self) -> Bytes {
+        self.data.clone()
+    }
+}
+
+/// Field paired with its sensitivity, for logging: `Debug` prints the value
+/// masked and with bytes outside printable ASCII escaped.
+///
+/// Built from a `(&FieldSpec, &Field)` pair, a spec left at
+/// `SensitivityType::Policy` masks everything, since resolving the policy
+/// needs the message spec; [`Message::masked`](crate::Message::masked)
+/// resolves it.
+#[derive(Clone, Copy)]
+pub struct MaskedField<'a> {
+    field: &'a Field,
+    sensitivity: SensitivityType,
+}
+
+impl<'a> MaskedField<'a> {
+    pub fn new(field: &'a Field, sensitivity: SensitivityType) -> Self {
+        let sensitivity = match sensitivity {
+            SensitivityType::Policy => SensitivityType::MaskAll,
+            sensitivity => sensitivity,
+        };
+        MaskedField { field, sensitivity }
+    }
+}
+
+impl<'a> From<(&'a FieldSpec, &'a Field)> for MaskedField<'a> {
+    fn from((spec, field): (&'a FieldSpec, &'a Field)) -> Self {
+        MaskedField::new(field, spec.sensitivity)
+    }
+}
+
+impl std::fmt::Debug for MaskedField<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let masked = self.sensitivity.mask(self.field.as_slice());
+        write!(f, "\"{}\"", masked.escape_ascii())
+    }
+}
+
+fn parse_digits(data: &[u8]) -> Result<u64, RS8583Error> {
+    if data.is_empty() {
+        return Err(RS8583Error::parse_error("Empty field is not a number"));
+    }
+    let invalid = || {
+        RS8583Error::parse_error(format!(
+            "Invalid number: \"{}\"",
+            String::from_utf8_lossy(data)
+        ))
+    };
+    data.iter().try_fold(0u64, |acc, &b| {
+        if !b.is_ascii_digit() {
+            return Err(invalid());
+        }
+        acc.checked_mul(10)
+            .and_then(|acc| acc.checked_add(u64::from(b - b'0')))
+            .ok_or_else(invalid)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_bytes_shared() {
+        let data = Bytes::from(b"0123456789ABCDEF".to_vec());
+        let field = Field::from_bytes(data.slice(4..8));
+        let first = field.bytes();
+        let second = field.bytes();
+
+        assert_eq!(first.as_ref(), b"4567");
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        assert_eq!(first.as_ptr(), data[4..].as_ptr());
+    }
+
+    #[test]
+    fn field_private_data() {
+        let field = Field::from_bytes(Bytes::from_static(b"TK4111111111111111 REF 0042\x00\xff"));
+        assert_eq!(
+            field.as_private_data(true),
+            "TK411111******1111 REF 0042\\x00\\xff"
+        );
+        assert_eq!(
+            field.as_private_data(false),
+            "TK4111111111111111 REF 0042\\x00\\xff"
+        );
+    }
+
+    #[test]
+    fn field_typed_accessors() {
+        let field = Field::from_bytes(Bytes::from_static(b"000000012500"));
+        assert_eq!(field.as_str().unwrap(), "000000012500");
+        assert_eq!(field.as_u64().unwrap(), 12500);
+        assert_eq!(field.as_i64().unwrap(), 12500);
+
+        let field = Field::from_bytes(Bytes::from_static(b"D00000150"));
+        assert_eq!(field.as_i64().unwrap(), -150);
+        assert!(field.as_u64().is_err());
+
+        let field = Field::from_bytes(Bytes::from_static(b"-9223372036854775808"));
+        assert_eq!(field.as_i64().unwrap(), i64::MIN);
+        let field = Field::from_bytes(Bytes::from_static(b"9223372036854775808"));
+        assert!(field.as_i64().is_err());
+        let field = Field::from_bytes(Bytes::from_static(b"99999999999999999999"));
+        assert!(field.as_u64().is_err());
+
+        let field = Field::from_bytes(Bytes::new());
+        assert_eq!(field.as_str().unwrap(), "");
+        assert_eq!(
+            field.as_u64(),
+            Err(RS8583Error::parse_error("Empty field is not a number"))
+        );
+        let field = Field::from_bytes(Bytes::from_static(b"12 4"));
+        assert_eq!(
+            field.as_u64(),
+            Err(RS8583Error::parse_error("Invalid number: \"12 4\""))
+        );
+        let field = Field::from_bytes(Bytes::from_static(b"\xff"));
+        assert!(field.as_str().is_err());
+    }
+
+    #[test]
+    fn field_masked_debug() {
+        let field = Field::from_bytes(Bytes::from_static(b"4111111111111111"));
+        let spec = FieldSpec {
+            sensitivity: SensitivityType::MaskPAN,
+            ..FieldSpec::default()
+        };
+        assert_eq!(
+            format!("{:?}", MaskedField::from((&spec, &field))),
+            "\"411111******1111\""
+        );
+
+        let spec = FieldSpec::default();
+        assert_eq!(
+            format!("{:?}", MaskedField::from((&spec, &field))),
+            "\"****************\""
+        );
+
+        let field = Field::from_bytes(Bytes::from_static(b"REF\x00\"1"));
+        assert_eq!(
+            format!("{:?}", MaskedField::new(&field, SensitivityType::Normal)),
+            "\"REF\\x00\\\"1\""
+        );
+    }
+
+    #[test]
+    fn field_as_tlv() {
+        let data = Bytes::from_static(b"\x9f\x27\x01\x80\x9f\x36\x02\x00\x01");
+        let field = Field::from_bytes(data.clone());
+        let objects = field.as_tlv().unwrap();
+        assert_eq!(objects[0], (Tag(0x9f27), Bytes::from_static(b"\x80")));
+        assert_eq!(objects[1].0, Tag(0x9f36));
+        assert_eq!(objects[1].1.as_ptr(), data[7..].as_ptr());
+    }
 }
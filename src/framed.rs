@@ -0,0 +1,181 @@
+//! Decoding and encoding framed message streams with `tokio_util::codec`.
+//!
+//! ```ignore
+//! let mut messages = FramedRead::new(socket, MessageCodec::new(spec, codec)?);
+//! while let Some(msg) = messages.next().await { ... }
+//! ```
+
+use bytes::{Buf, BytesMut};
+use std::io;
+use std::sync::Arc;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::codec::{Codec, Framing};
+use crate::error::RS8583Error;
+use crate::msg::Message;
+use crate::spec::MessageSpec;
+
+/// Default limit on the declared length of an incoming frame.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// Stream codec yielding one message per frame. Decoded messages share
+/// ownership of the spec, so they can be handed off to other tasks.
+/// Zero-length frames (keep-alives) are skipped. A frame that fails to parse
+/// is consumed before the error is returned, while a frame whose header
+/// declares more than the maximum frame length fails the stream.
+pub struct MessageCodec {
+    spec: Arc<MessageSpec>,
+    codec: Codec,
+    max_frame_len: usize,
+}
+
+impl MessageCodec {
+    /// Fails for unframed codecs, as unframed messages carry no boundaries
+    /// to wait for.
+    pub fn new(spec: Arc<MessageSpec>, codec: Codec) -> Result<Self, RS8583Error> {
+        codec.validate()?;
+        if let Framing::Unframed = codec.framing {
+            return Err(RS8583Error::config_error(
+                "Message streams need a framed codec",
+            ));
+        }
+        Ok(MessageCodec {
+            spec,
+            codec,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        })
+    }
+
+    /// Set the largest frame length, excluding the header, that will be
+    /// accepted from the peer.
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+}
+
+fn invalid(e: RS8583Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+impl Decoder for MessageCodec {
+    type Item = Message<'static>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let header_len = self.codec.frame_header_len();
+        loop {
+            let len = match self.codec.peek_frame_len(src).map_err(invalid)? {
+                Some(len) if len > self.max_frame_len => {
+                    return Err(invalid(RS8583Error::parse_error(format!(
+                        "Frame length {} exceeds the maximum of {}",
+                        len, self.max_frame_len
+                    ))));
+                }
+                Some(len) => header_len + len,
+                None => return Ok(None),
+            };
+            if src.len() < len {
+                return Ok(None);
+            }
+            let mut frame = src.split_to(len).freeze();
+            frame.advance(header_len);
+            if !frame.is_empty() {
+                return Message::from_frame_owned(self.spec.clone(), &self.codec, frame)
+                    .map(Some)
+                    .map_err(invalid);
+            }
+        }
+    }
+}
+
+impl Encoder<Message<'_>> for MessageCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: Message<'_>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let buf = msg.serialize(&self.codec).map_err(invalid)?;
+        dst.extend_from_slice(&buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::tests::batch_spec;
+
+    fn stream_spec() -> Arc<MessageSpec> {
+        Arc::new(batch_spec())
+    }
+
+    #[test]
+    fn framed_decode_stream() {
+        let codec = Codec {
+            framing: Framing::MHeader,
+            ..Codec::default()
+        };
+        let mut decoder = MessageCodec::new(stream_spec(), codec).unwrap();
+        let mut src = BytesMut::from(
+            &b"\x00\x120200\x04\x00\x00\x00\x00\x00\x00\x00000000\
+               \x00\x00\
+               \x00\x120210\x04\x00\x00\x00\x00\x00\x00\x00300000\
+               \x00\x12"[..],
+        );
+
+        let first = decoder.decode(&mut src).unwrap().unwrap();
+        assert_eq!(first.mti().as_bytes(), b"0200");
+        let second = decoder.decode(&mut src).unwrap().unwrap();
+        assert_eq!(second.mti().as_bytes(), b"0210");
+        assert_eq!(second.field(2).unwrap().as_slice(), b"300000");
+
+        // Partial frame: wait for the rest
+        assert!(decoder.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(b"0800\x04\x00\x00\x00");
+        assert!(decoder.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(b"\x00\x00\x00\x00990000");
+        let third = decoder.decode(&mut src).unwrap().unwrap();
+        assert_eq!(third.mti().as_bytes(), b"0800");
+        assert!(src.is_empty());
+
+        let mut dst = BytesMut::new();
+        decoder.encode(first, &mut dst).unwrap();
+        assert_eq!(
+            &dst[..],
+            &b"\x00\x120200\x04\x00\x00\x00\x00\x00\x00\x00000000"[..]
+        );
+    }
+
+    #[test]
+    fn framed_max_frame_len() {
+        let codec = Codec {
+            framing: Framing::VHeader {
+                width: 4,
+                ascii: false,
+            },
+            ..Codec::default()
+        };
+        let mut decoder = MessageCodec::new(stream_spec(), codec).unwrap();
+        let mut src = BytesMut::from(&b"\xff\xff\xff\xff"[..]);
+        let err = decoder.decode(&mut src).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "ISO8583 parse error: Frame length 4294967295 exceeds the maximum of 65536"
+        );
+        assert!(src.capacity() < 1024);
+
+        let mut decoder = decoder.with_max_frame_len(10);
+        let mut src = BytesMut::from(&b"\x00\x00\x00\x120200"[..]);
+        assert!(decoder.decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn framed_unframed_codec() {
+        assert_eq!(
+            MessageCodec::new(stream_spec(), Codec::default()).err(),
+            Some(RS8583Error::config_error(
+                "Message streams need a framed codec"
+            ))
+        );
+    }
+}
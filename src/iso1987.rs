@@ -0,0 +1,221 @@
+//! Built-in ISO 8583:1987 field dictionary.
+//!
+//! Fields of type `x+n` (amount with a C/D sign) are declared `AN`, track 2
+//! and 3 data (`z`) `ANS`, and binary fields of 64 bits 8-byte `B` fields.
+//! DE1 and DE65 are the secondary and tertiary bitmaps.
+
+use crate::spec::{FieldSpec, FieldType, LengthType, MessageSpec};
+
+use FieldType::*;
+use LengthType::*;
+
+/// Data element number, name, type, length type and (maximum) length.
+const FIELDS: &[(usize, &str, FieldType, LengthType, usize)] = &[
+    (1, "BITMAP, SECONDARY", B, BitMap, 8),
+    (2, "PRIMARY ACCOUNT NUMBER", N, LLVar, 19),
+    (3, "PROCESSING CODE", N, Fixed, 6),
+    (4, "AMOUNT, TRANSACTION", N, Fixed, 12),
+    (5, "AMOUNT, SETTLEMENT", N, Fixed, 12),
+    (6, "AMOUNT, CARDHOLDER BILLING", N, Fixed, 12),
+    (7, "TRANSMISSION DATE AND TIME", N, Fixed, 10),
+    (8, "AMOUNT, CARDHOLDER BILLING FEE", N, Fixed, 8),
+    (9, "CONVERSION RATE, SETTLEMENT", N, Fixed, 8),
+    (10, "CONVERSION RATE, CARDHOLDER BILLING", N, Fixed, 8),
+    (11, "SYSTEMS TRACE AUDIT NUMBER", N, Fixed, 6),
+    (12, "TIME, LOCAL TRANSACTION", N, Fixed, 6),
+    (13, "DATE, LOCAL TRANSACTION", N, Fixed, 4),
+    (14, "DATE, EXPIRATION", N, Fixed, 4),
+    (15, "DATE, SETTLEMENT", N, Fixed, 4),
+    (16, "DATE, CONVERSION", N, Fixed, 4),
+    (17, "DATE, CAPTURE", N, Fixed, 4),
+    (18, "MERCHANT TYPE", N, Fixed, 4),
+    (19, "ACQUIRING INSTITUTION COUNTRY CODE", N, Fixed, 3),
+    (20, "PAN EXTENDED, COUNTRY CODE", N, Fixed, 3),
+    (21, "FORWARDING INSTITUTION COUNTRY CODE", N, Fixed, 3),
+    (22, "POINT OF SERVICE ENTRY MODE", N, Fixed, 3),
+    (23, "APPLICATION PAN SEQUENCE NUMBER", N, Fixed, 3),
+    (24, "NETWORK INTERNATIONAL IDENTIFIER", N, Fixed, 3),
+    (25, "POINT OF SERVICE CONDITION CODE", N, Fixed, 2),
+    (26, "POINT OF SERVICE CAPTURE CODE", N, Fixed, 2),
+    (
+        27,
+        "AUTHORIZING IDENTIFICATION RESPONSE LENGTH",
+        N,
+        Fixed,
+        1,
+    ),
+    (28, "AMOUNT, TRANSACTION FEE", AN, Fixed, 9),
+    (29, "AMOUNT, SETTLEMENT FEE", AN, Fixed, 9),
+    (30, "AMOUNT, TRANSACTION PROCESSING FEE", AN, Fixed, 9),
+    (31, "AMOUNT, SETTLEMENT PROCESSING FEE", AN, Fixed, 9),
+    (
+        32,
+        "ACQUIRING INSTITUTION IDENTIFICATION CODE",
+        N,
+        LLVar,
+        11,
+    ),
+    (
+        33,
+        "FORWARDING INSTITUTION IDENTIFICATION CODE",
+        N,
+        LLVar,
+        11,
+    ),
+    (34, "PRIMARY ACCOUNT NUMBER, EXTENDED", NS, LLVar, 28),
+    (35, "TRACK 2 DATA", ANS, LLVar, 37),
+    (36, "TRACK 3 DATA", ANS, LLLVar, 104),
+    (37, "RETRIEVAL REFERENCE NUMBER", AN, Fixed, 12),
+    (38, "AUTHORIZATION IDENTIFICATION RESPONSE", AN, Fixed, 6),
+    (39, "RESPONSE CODE", AN, Fixed, 2),
+    (40, "SERVICE RESTRICTION CODE", AN, Fixed, 3),
+    (41, "CARD ACCEPTOR TERMINAL IDENTIFICATION", ANS, Fixed, 8),
+    (42, "CARD ACCEPTOR IDENTIFICATION CODE", ANS, Fixed, 15),
+    (43, "CARD ACCEPTOR NAME/LOCATION", ANS, Fixed, 40),
+    (44, "ADDITIONAL RESPONSE DATA", AN, LLVar, 25),
+    (45, "TRACK 1 DATA", ANS, LLVar, 76),
+    (46, "ADDITIONAL DATA - ISO", ANS, LLLVar, 999),
+    (47, "ADDITIONAL DATA - NATIONAL", ANS, LLLVar, 999),
+    (48, "ADDITIONAL DATA - PRIVATE", ANS, LLLVar, 999),
+    (49, "CURRENCY CODE, TRANSACTION", AN, Fixed, 3),
+    (50, "CURRENCY CODE, SETTLEMENT", AN, Fixed, 3),
+    (51, "CURRENCY CODE, CARDHOLDER BILLING", AN, Fixed, 3),
+    (52, "PERSONAL IDENTIFICATION NUMBER DATA", B, Fixed, 8),
+    (53, "SECURITY RELATED CONTROL INFORMATION", N, Fixed, 16),
+    (54, "ADDITIONAL AMOUNTS", AN, LLLVar, 120),
+    (55, "RESERVED ISO 55", ANS, LLLVar, 999),
+    (56, "RESERVED ISO 56", ANS, LLLVar, 999),
+    (57, "RESERVED NATIONAL 57", ANS, LLLVar, 999),
+    (58, "RESERVED NATIONAL 58", ANS, LLLVar, 999),
+    (59, "RESERVED NATIONAL 59", ANS, LLLVar, 999),
+    (60, "RESERVED NATIONAL 60", ANS, LLLVar, 999),
+    (61, "RESERVED PRIVATE 61", ANS, LLLVar, 999),
+    (62, "RESERVED PRIVATE 62", ANS, LLLVar, 999),
+    (63, "RESERVED PRIVATE 63", ANS, LLLVar, 999),
+    (64, "PRIMARY MESSAGE AUTHENTICATION CODE", B, Fixed, 8),
+    (65, "BITMAP, TERTIARY", B, BitMap, 8),
+    (66, "SETTLEMENT CODE", N, Fixed, 1),
+    (67, "EXTENDED PAYMENT CODE", N, Fixed, 2),
+    (68, "RECEIVING INSTITUTION COUNTRY CODE", N, Fixed, 3),
+    (69, "SETTLEMENT INSTITUTION COUNTRY CODE", N, Fixed, 3),
+    (70, "NETWORK MANAGEMENT INFORMATION CODE", N, Fixed, 3),
+    (71, "MESSAGE NUMBER", N, Fixed, 4),
+    (72, "MESSAGE NUMBER, LAST", N, Fixed, 4),
+    (73, "DATE, ACTION", N, Fixed, 6),
+    (74, "CREDITS, NUMBER", N, Fixed, 10),
+    (75, "CREDITS REVERSAL, NUMBER", N, Fixed, 10),
+    (76, "DEBITS, NUMBER", N, Fixed, 10),
+    (77, "DEBITS REVERSAL, NUMBER", N, Fixed, 10),
+    (78, "TRANSFER, NUMBER", N, Fixed, 10),
+    (79, "TRANSFER REVERSAL, NUMBER", N, Fixed, 10),
+    (80, "INQUIRIES, NUMBER", N, Fixed, 10),
+    (81, "AUTHORIZATIONS, NUMBER", N, Fixed, 10),
+    (82, "CREDITS, PROCESSING FEE AMOUNT", N, Fixed, 12),
+    (83, "CREDITS, TRANSACTION FEE AMOUNT", N, Fixed, 12),
+    (84, "DEBITS, PROCESSING FEE AMOUNT", N, Fixed, 12),
+    (85, "DEBITS, TRANSACTION FEE AMOUNT", N, Fixed, 12),
+    (86, "CREDITS, AMOUNT", N, Fixed, 16),
+    (87, "CREDITS REVERSAL, AMOUNT", N, Fixed, 16),
+    (88, "DEBITS, AMOUNT", N, Fixed, 16),
+    (89, "DEBITS REVERSAL, AMOUNT", N, Fixed, 16),
+    (90, "ORIGINAL DATA ELEMENTS", N, Fixed, 42),
+    (91, "FILE UPDATE CODE", AN, Fixed, 1),
+    (92, "FILE SECURITY CODE", AN, Fixed, 2),
+    (93, "RESPONSE INDICATOR", AN, Fixed, 5),
+    (94, "SERVICE INDICATOR", AN, Fixed, 7),
+    (95, "REPLACEMENT AMOUNTS", AN, Fixed, 42),
+    (96, "MESSAGE SECURITY CODE", B, Fixed, 8),
+    (97, "AMOUNT, NET SETTLEMENT", AN, Fixed, 17),
+    (98, "PAYEE", ANS, Fixed, 25),
+    (
+        99,
+        "SETTLEMENT INSTITUTION IDENTIFICATION CODE",
+        N,
+        LLVar,
+        11,
+    ),
+    (
+        100,
+        "RECEIVING INSTITUTION IDENTIFICATION CODE",
+        N,
+        LLVar,
+        11,
+    ),
+    (101, "FILE NAME", ANS, LLVar, 17),
+    (102, "ACCOUNT IDENTIFICATION 1", ANS, LLVar, 28),
+    (103, "ACCOUNT IDENTIFICATION 2", ANS, LLVar, 28),
+    (104, "TRANSACTION DESCRIPTION", ANS, LLLVar, 100),
+    (105, "RESERVED ISO 105", ANS, LLLVar, 999),
+    (106, "RESERVED ISO 106", ANS, LLLVar, 999),
+    (107, "RESERVED ISO 107", ANS, LLLVar, 999),
+    (108, "RESERVED ISO 108", ANS, LLLVar, 999),
+    (109, "RESERVED ISO 109", ANS, LLLVar, 999),
+    (110, "RESERVED ISO 110", ANS, LLLVar, 999),
+    (111, "RESERVED ISO 111", ANS, LLLVar, 999),
+    (112, "RESERVED NATIONAL 112", ANS, LLLVar, 999),
+    (113, "RESERVED NATIONAL 113", ANS, LLLVar, 999),
+    (114, "RESERVED NATIONAL 114", ANS, LLLVar, 999),
+    (115, "RESERVED NATIONAL 115", ANS, LLLVar, 999),
+    (116, "RESERVED NATIONAL 116", ANS, LLLVar, 999),
+    (117, "RESERVED NATIONAL 117", ANS, LLLVar, 999),
+    (118, "RESERVED NATIONAL 118", ANS, LLLVar, 999),
+    (119, "RESERVED NATIONAL 119", ANS, LLLVar, 999),
+    (120, "RESERVED PRIVATE 120", ANS, LLLVar, 999),
+    (121, "RESERVED PRIVATE 121", ANS, LLLVar, 999),
+    (122, "RESERVED PRIVATE 122", ANS, LLLVar, 999),
+    (123, "RESERVED PRIVATE 123", ANS, LLLVar, 999),
+    (124, "RESERVED PRIVATE 124", ANS, LLLVar, 999),
+    (125, "RESERVED PRIVATE 125", ANS, LLLVar, 999),
+    (126, "RESERVED PRIVATE 126", ANS, LLLVar, 999),
+    (127, "RESERVED PRIVATE 127", ANS, LLLVar, 999),
+    (128, "SECONDARY MESSAGE AUTHENTICATION CODE", B, Fixed, 8),
+];
+
+impl MessageSpec {
+    /// Standard ISO 8583:1987 field definitions for DE1 to DE128.
+    pub fn iso8583_1987() -> MessageSpec {
+        let fields = FIELDS
+            .iter()
+            .map(|(_, name, field_type, length_type, length)| {
+                Some(FieldSpec {
+                    name: String::from(*name),
+                    field_type: *field_type,
+                    length_type: *length_type,
+                    length: *length,
+                    ..FieldSpec::default()
+                })
+            })
+            .collect();
+        MessageSpec {
+            fields,
+            ..MessageSpec::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso1987_fields() {
+        assert!(FIELDS.iter().enumerate().all(|(idx, f)| f.0 == idx + 1));
+
+        let spec = MessageSpec::iso8583_1987();
+        spec.validate().unwrap();
+        assert_eq!(spec.fields.len(), 128);
+        let def = |de: usize| {
+            let fs = spec.fields[de - 1].as_ref().unwrap();
+            (fs.field_type, fs.length_type, fs.length)
+        };
+        assert_eq!(def(2), (N, LLVar, 19));
+        assert_eq!(def(3), (N, Fixed, 6));
+        assert_eq!(def(4), (N, Fixed, 12));
+        assert_eq!(def(7), (N, Fixed, 10));
+        assert_eq!(def(35), (ANS, LLVar, 37));
+        assert_eq!(def(39), (AN, Fixed, 2));
+        assert_eq!(def(52), (B, Fixed, 8));
+        assert_eq!(def(90), (N, Fixed, 42));
+        assert_eq!(def(128), (B, Fixed, 8));
+    }
+}
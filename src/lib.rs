@@ -1,10 +1,30 @@
+pub mod batch;
 pub mod bitmap;
 pub mod codec;
+pub mod de;
 pub mod error;
 pub mod field;
+#[cfg(feature = "async")]
+pub mod framed;
+#[cfg(feature = "iso1987")]
+mod iso1987;
+#[cfg(feature = "iso4217")]
+pub mod iso4217;
 pub mod msg;
 pub mod spec;
+mod store;
+pub mod subfield;
+pub mod tlv;
 
-pub use crate::codec::{Codec, Encoding, Framing, VariableLengthFormat};
+#[cfg(feature = "derive")]
+pub use rs8583_derive::DataElements;
+
+pub use crate::batch::BatchReader;
+pub use crate::codec::{
+    BcdPad, BitmapEncoding, Codec, Encoding, FieldOrder, Framing, HeaderOrder, LengthPadding,
+    MTIEncoding, UnspecifiedField, VariableLengthFormat,
+};
+#[cfg(feature = "async")]
+pub use crate::framed::MessageCodec;
 pub use crate::msg::{Message, MTI};
-pub use crate::spec::{FieldSpec, MessageSpec};
+pub use crate::spec::{FieldSpec, MessageSpec, SpecRegistry};
@@ -1,11 +1,20 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use encoding8::{ascii, ebcdic};
+use std::borrow::Cow;
+use std::ops::{Deref, Range};
+use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use crate::bitmap::BitMap;
-use crate::codec::Codec;
+use crate::codec::{self, Codec, Framing, HeaderOrder, MTIEncoding, UnspecifiedField};
 use crate::error::RS8583Error;
-use crate::field::Field;
-use crate::spec::MessageSpec;
+use crate::field::{Field, MaskedField};
+use crate::spec::{FieldSpec, MessageSpec, SensitivityType, SpecRegistry};
+use crate::store::FieldStore;
 
+#[derive(Clone)]
 pub struct MTI([u8; 4]);
 
 impl Default for MTI {
@@ -15,15 +24,90 @@ impl Default for MTI {
 }
 
 impl MTI {
-    fn from_cursor(cursor: &mut Bytes) -> Result<MTI, RS8583Error> {
-        if cursor.remaining() < 4 {
+    fn from_cursor(codec: &Codec, cursor: &mut Bytes) -> Result<MTI, RS8583Error> {
+        let wire_len = match codec.mti_encoding {
+            MTIEncoding::BCD => 2,
+            _ => 4,
+        };
+        if cursor.remaining() < wire_len {
             return Err(RS8583Error::parse_error("Truncated MTI"));
         }
         let mut mti = MTI::default();
-        cursor.copy_to_slice(&mut mti.0);
+        match codec.mti_encoding {
+            MTIEncoding::ASCII => cursor.copy_to_slice(&mut mti.0),
+            MTIEncoding::EBCDIC => {
+                cursor.copy_to_slice(&mut mti.0);
+                for ch in mti.0.iter_mut() {
+                    *ch = ebcdic::to_ascii(*ch);
+                }
+            }
+            MTIEncoding::BCD => {
+                for pair in mti.0.chunks_mut(2) {
+                    let byte = cursor.get_u8();
+                    for (ch, nibble) in pair.iter_mut().zip(&[byte >> 4, byte & 0x0f]) {
+                        if *nibble > 9 {
+                            return Err(RS8583Error::parse_error(format!(
+                                "Invalid BCD MTI byte: 0x{:02x}",
+                                byte
+                            )));
+                        }
+                        *ch = b'0' + nibble;
+                    }
+                }
+            }
+        }
+        if codec.lenient_mti {
+            let digits: Vec<u8> = mti
+                .0
+                .iter()
+                .copied()
+                .filter(|ch| !ch.is_ascii_whitespace())
+                .collect();
+            let start = mti.0.len() - digits.len();
+            mti.0 = *b"0000";
+            mti.0[start..].copy_from_slice(&digits);
+        }
+        if let Some(ch) = mti.0.iter().find(|ch| !ch.is_ascii_digit()) {
+            return Err(RS8583Error::parse_error(format!(
+                "MTI contains non-digit 0x{:02x}",
+                ch
+            )));
+        }
         Ok(mti)
     }
 
+    fn serialize(&self, codec: &Codec, buf: &mut BytesMut) {
+        match codec.mti_encoding {
+            MTIEncoding::ASCII => buf.put(self.0.as_ref()),
+            MTIEncoding::EBCDIC => {
+                for ch in self.0.iter() {
+                    buf.put_u8(ascii::to_ebcdic(*ch));
+                }
+            }
+            MTIEncoding::BCD => {
+                for pair in self.0.chunks(2) {
+                    buf.put_u8(((pair[0] & 0x0f) << 4) | (pair[1] & 0x0f));
+                }
+            }
+        }
+    }
+
+    /// MTI from its four ASCII digits, e.g. `MTI::new(b'0', b'2', b'0', b'0')`.
+    pub fn new(version: u8, class: u8, function: u8, origin: u8) -> Result<MTI, RS8583Error> {
+        let digits = [version, class, function, origin];
+        if let Some(ch) = digits.iter().find(|ch| !ch.is_ascii_digit()) {
+            return Err(RS8583Error::parse_error(format!(
+                "MTI contains non-digit 0x{:02x}",
+                ch
+            )));
+        }
+        Ok(MTI(digits))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 4] {
+        &self.0
+    }
+
     pub fn version_byte(&self) -> u8 {
         self.0[0]
     }
@@ -137,142 +221,893 @@ impl MTI {
     }
 
     pub fn is_from_acquirer(&self) -> bool {
-        match self.origin_byte() {
-            b'0' | b'1' => true,
-            _ => false,
-        }
+        matches!(self.origin_byte(), b'0' | b'1')
     }
 
     pub fn is_from_issuer(&self) -> bool {
-        match self.origin_byte() {
-            b'2' | b'3' => true,
-            _ => false,
-        }
+        matches!(self.origin_byte(), b'2' | b'3')
     }
 
     pub fn is_from_other(&self) -> bool {
-        match self.origin_byte() {
-            b'4' | b'5' => true,
-            _ => false,
-        }
+        matches!(self.origin_byte(), b'4' | b'5')
     }
 
     pub fn is_repeat(&self) -> bool {
-        match self.origin_byte() {
-            b'1' | b'3' | b'5' => true,
-            _ => false,
+        matches!(self.origin_byte(), b'1' | b'3' | b'5')
+    }
+
+    /// Advice MTI corresponding to an authorization or financial request,
+    /// e.g. 0100 to 0120. Version and origin are kept.
+    pub fn to_advice(&self) -> Result<MTI, RS8583Error> {
+        if !(self.is_authorization() || self.is_financial()) || !self.is_request() {
+            return Err(RS8583Error::parse_error(format!(
+                "MTI {} is not an authorization or financial request",
+                String::from_utf8_lossy(&self.0)
+            )));
+        }
+        let mut mti = self.clone();
+        mti.0[2] = b'2';
+        Ok(mti)
+    }
+
+    /// Response MTI for a message expecting one, incrementing the function
+    /// digit:
+    ///
+    /// | function          | response              | example     |
+    /// |-------------------|-----------------------|-------------|
+    /// | 0 request         | 1 request response    | 0200 → 0210 |
+    /// | 2 advice          | 3 advice response     | 0420 → 0430 |
+    /// | 4 notification    | 5 notification ack    | 0644 → 0654 |
+    /// | 6 instruction     | 7 instruction ack     | 0366 → 0376 |
+    ///
+    /// Version, class and origin are kept: the origin digit of a response
+    /// conventionally repeats that of the message answered, so flipping it
+    /// to the responder side would turn 0200 into 0212. Responses and
+    /// acknowledgements themselves have no response.
+    pub fn to_response(&self) -> Result<MTI, RS8583Error> {
+        let function = match self.function_byte() {
+            b'0' => b'1',
+            b'2' => b'3',
+            b'4' => b'5',
+            b'6' => b'7',
+            _ => {
+                return Err(RS8583Error::parse_error(format!(
+                    "MTI {} has no response",
+                    String::from_utf8_lossy(&self.0)
+                )))
+            }
+        };
+        let mut mti = self.clone();
+        mti.0[2] = function;
+        Ok(mti)
+    }
+
+    /// Reversal MTI for an authorization or financial request or advice,
+    /// e.g. 0200 to 0400 and 0220 to 0420. Version, function and origin are
+    /// kept.
+    pub fn to_reversal(&self) -> Result<MTI, RS8583Error> {
+        if !(self.is_authorization() || self.is_financial())
+            || !(self.is_request() || self.is_advice())
+        {
+            return Err(RS8583Error::parse_error(format!(
+                "MTI {} is not an authorization or financial request or advice",
+                String::from_utf8_lossy(&self.0)
+            )));
+        }
+        let mut mti = self.clone();
+        mti.0[1] = b'4';
+        Ok(mti)
+    }
+}
+
+impl FromStr for MTI {
+    type Err = RS8583Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match *s.as_bytes() {
+            [version, class, function, origin] => MTI::new(version, class, function, origin),
+            _ => Err(RS8583Error::parse_error(format!(
+                "MTI must be 4 digits, got \"{}\"",
+                s
+            ))),
         }
     }
 }
 
 // TODO: buffer size checks, everywhere
 
+/// Highest field index a message can hold: DE192, the last field of a
+/// tertiary bitmap.
+pub const MAX_FIELD_INDEX: usize = 191;
+
+fn check_field_index(idx: usize) -> Result<(), RS8583Error> {
+    if idx % 64 == 0 {
+        return Err(RS8583Error::parse_error(format!(
+            "field {} is a bitmap continuation bit",
+            idx
+        )));
+    }
+    if idx > MAX_FIELD_INDEX {
+        return Err(RS8583Error::parse_error(format!(
+            "field {} out of range (max {})",
+            idx, MAX_FIELD_INDEX
+        )));
+    }
+    Ok(())
+}
+
+/// Spec of a message: borrowed, or shared for messages that must outlive
+/// the scope of the spec.
+#[derive(Clone)]
+enum SpecRef<'spec> {
+    Borrowed(&'spec MessageSpec),
+    Shared(Arc<MessageSpec>),
+}
+
+impl Deref for SpecRef<'_> {
+    type Target = MessageSpec;
+
+    fn deref(&self) -> &MessageSpec {
+        match self {
+            Self::Borrowed(spec) => spec,
+            Self::Shared(spec) => spec,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Message<'spec> {
     mti: MTI,
     bitmap: BitMap,
-    spec: &'spec MessageSpec,
-    fields: Vec<Option<Field>>,
+    spec: SpecRef<'spec>,
+    fields: FieldStore,
 }
 
 impl<'spec> Message<'spec> {
+    /// Empty message with MTI 0000, to be filled in field by field.
+    pub fn new(spec: &'spec MessageSpec) -> Self {
+        Self::with_mti(spec, MTI::default())
+    }
+
+    pub fn with_mti(spec: &'spec MessageSpec, mti: MTI) -> Self {
+        Message {
+            mti,
+            bitmap: BitMap::new(),
+            spec: SpecRef::Borrowed(spec),
+            fields: FieldStore::new(),
+        }
+    }
+
+    /// Parse a complete message, framing header and LRC trailer included if
+    /// the codec uses them. The frame must span all of `data`.
     pub fn from_bytes(
         spec: &'spec MessageSpec,
         codec: &Codec,
         mut data: Bytes,
     ) -> Result<Self, RS8583Error> {
-        let mti = MTI::from_cursor(&mut data)?;
-        let bitmap = BitMap::from_cursor(&mut data)?;
-        let fields = Self::parse_fields(spec, codec, &bitmap, &mut data)?;
+        if let Framing::Unframed = codec.framing {
+            return Self::from_frame(spec, codec, data);
+        }
+        let frame = codec.split_frame(&mut data)?;
+        if !data.is_empty() {
+            return Err(RS8583Error::parse_error(format!(
+                "Frame length {} disagrees with message length {}",
+                frame.len(),
+                frame.len() + data.len()
+            )));
+        }
+        Self::from_frame(spec, codec, frame)
+    }
+
+    /// Parse a message body without framing header, as returned by
+    /// [`Codec::split_frame`], checking the LRC trailer if enabled.
+    pub fn from_frame(
+        spec: &'spec MessageSpec,
+        codec: &Codec,
+        mut data: Bytes,
+    ) -> Result<Self, RS8583Error> {
+        if codec.lrc {
+            if data.is_empty() {
+                return Err(RS8583Error::parse_error("Missing LRC"));
+            }
+            let trailer = data.split_off(data.len() - 1);
+            if codec::lrc(&data) != trailer[0] {
+                return Err(RS8583Error::parse_error("LRC mismatch"));
+            }
+        }
+        Self::from_cursor(spec, codec, &mut data)
+    }
+
+    /// Like [`Message::from_bytes`], but the message shares ownership of
+    /// `spec` instead of borrowing it, so it can outlive the caller.
+    pub fn from_bytes_owned(
+        spec: Arc<MessageSpec>,
+        codec: &Codec,
+        data: Bytes,
+    ) -> Result<Message<'static>, RS8583Error> {
+        let msg = Message::from_bytes(&spec, codec, data)?;
+        Ok(msg.with_shared_spec(spec.clone()))
+    }
+
+    /// Like [`Message::from_frame`], but the message shares ownership of
+    /// `spec` instead of borrowing it, so it can outlive the caller.
+    pub fn from_frame_owned(
+        spec: Arc<MessageSpec>,
+        codec: &Codec,
+        data: Bytes,
+    ) -> Result<Message<'static>, RS8583Error> {
+        let msg = Message::from_frame(&spec, codec, data)?;
+        Ok(msg.with_shared_spec(spec.clone()))
+    }
+
+    /// Empty message sharing ownership of `spec`, see [`Message::new`].
+    pub fn new_owned(spec: Arc<MessageSpec>) -> Message<'static> {
+        Message {
+            mti: MTI::default(),
+            bitmap: BitMap::new(),
+            spec: SpecRef::Shared(spec),
+            fields: FieldStore::new(),
+        }
+    }
+
+    /// This message with its spec replaced by `spec`, which must be the spec
+    /// it was built with.
+    fn with_shared_spec(self, spec: Arc<MessageSpec>) -> Message<'static> {
+        Message {
+            mti: self.mti,
+            bitmap: self.bitmap,
+            spec: SpecRef::Shared(spec),
+            fields: self.fields,
+        }
+    }
+
+    /// Parse a single message from the start of `cursor`, leaving any
+    /// following bytes in place. Does not handle the LRC trailer, which can
+    /// only be located by [`Message::from_frame`].
+    pub fn from_cursor(
+        spec: &'spec MessageSpec,
+        codec: &Codec,
+        cursor: &mut Bytes,
+    ) -> Result<Self, RS8583Error> {
+        let (mti, bitmap) = match codec.header_order {
+            HeaderOrder::MTIFirst => {
+                let mti = MTI::from_cursor(codec, cursor)?;
+                (mti, BitMap::from_cursor(codec, cursor)?)
+            }
+            HeaderOrder::BitmapFirst => {
+                let bitmap = BitMap::from_cursor(codec, cursor)?;
+                (MTI::from_cursor(codec, cursor)?, bitmap)
+            }
+        };
+        let fields = Self::parse_fields(spec, codec, &bitmap, cursor)?;
         Ok(Message {
             mti,
             bitmap,
-            spec,
+            spec: SpecRef::Borrowed(spec),
             fields,
         })
     }
 
     fn parse_fields(
-        spec: &'spec MessageSpec,
+        spec: &MessageSpec,
         codec: &Codec,
         bitmap: &BitMap,
         cursor: &mut Bytes,
-    ) -> Result<Vec<Option<Field>>, RS8583Error> {
-        let mut fields = vec![None; 128];
+    ) -> Result<FieldStore, RS8583Error> {
+        let mut fields = FieldStore::for_bitmap(bitmap);
+
+        for idx in codec.field_order.order(bitmap) {
+            // Without a spec the field length is unknown, so nothing after
+            // it could be parsed reliably.
+            let field_spec = match spec.fields.get(idx) {
+                Some(Some(field_spec)) => field_spec,
+                _ => {
+                    return Err(RS8583Error::parse_error(format!(
+                        "field {} set but not defined in spec, cannot determine its length",
+                        idx
+                    )))
+                }
+            };
+            let field = Self::parse_field(spec, codec, idx, field_spec, cursor)
+                .map_err(|e| RS8583Error::field_parse_error(idx, &field_spec.name, e))?;
+            fields.set(idx, field);
+        }
 
-        for idx in bitmap.iter_set() {
-            let field_spec = spec.fields.get(idx).unwrap();
-            if field_spec.is_none() {
-                // WARN
-                continue;
+        Ok(fields)
+    }
+
+    fn parse_field(
+        spec: &MessageSpec,
+        codec: &Codec,
+        idx: usize,
+        field_spec: &FieldSpec,
+        cursor: &mut Bytes,
+    ) -> Result<Field, RS8583Error> {
+        // Errors are attributed to the field by the caller
+        let field = field_spec.parse_value(codec, cursor)?;
+        if codec.strict_field_validation {
+            field_spec.check_value(field.as_slice())?;
+            spec.run_validators(idx, &field)?;
+        }
+        Ok(field)
+    }
+
+    /// Re-resolve the spec from the current MTI, checking that all present
+    /// fields conform to it. Field data is kept as is; on error the message is
+    /// left unchanged.
+    pub fn reinterpret(&mut self, registry: &'spec SpecRegistry) -> Result<(), RS8583Error> {
+        let spec = registry.resolve(&self.mti).ok_or_else(|| {
+            RS8583Error::parse_error(format!(
+                "No spec registered for MTI {}",
+                String::from_utf8_lossy(self.mti.as_bytes())
+            ))
+        })?;
+        for idx in self.bitmap.iter_set() {
+            let field = match self.field(idx) {
+                Some(field) => field,
+                None => continue,
+            };
+            match spec.fields.get(idx) {
+                Some(Some(field_spec)) => {
+                    field_spec.validate_value(idx, field.as_slice())?;
+                    spec.validate_field(idx, field)?;
+                }
+                _ => {
+                    return Err(RS8583Error::parse_error(format!(
+                        "field {} not defined in spec",
+                        idx
+                    )))
+                }
             }
-            let field_spec = field_spec.as_ref().unwrap();
-            let to_read = field_spec.to_read(codec, cursor)?;
-            if cursor.remaining() < to_read {
-                // TODO: better error
-                return Err(RS8583Error::parse_error("Truncated field"));
+        }
+        self.spec = SpecRef::Borrowed(spec);
+        Ok(())
+    }
+
+    /// Check all present fields (length, field type character set and spec
+    /// validators), field dependencies and the presence policy for the MTI,
+    /// collecting every problem found. Fields without a spec are reported too.
+    pub fn validate(&self) -> Result<(), Vec<RS8583Error>> {
+        let mut errors = Vec::new();
+        for (idx, field) in self.iter_fields() {
+            match self.spec.fields.get(idx) {
+                Some(Some(field_spec)) => {
+                    errors.extend(field_spec.validate_value(idx, field.as_slice()).err());
+                    errors.extend(self.spec.validate_field(idx, field).err());
+                }
+                _ => errors.push(RS8583Error::parse_error(format!(
+                    "field {} not defined in spec",
+                    idx
+                ))),
             }
-            fields[idx] = Some(Field::from_bytes(cursor.slice(..to_read)));
-            cursor.advance(to_read);
         }
+        errors.extend(self.spec.check_dependencies(|idx| self.bitmap.test(idx)));
+        if let Some(policy) = self.spec.presence_policy(&self.mti) {
+            errors.extend(policy.check(&self.mti, &self.bitmap));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 
-        Ok(fields)
+    /// Clone a prototype message, to be filled in with per-transaction fields.
+    pub fn from_template(&self) -> Message<'spec> {
+        self.clone()
     }
 
     pub fn mti(&self) -> &MTI {
         &self.mti
     }
 
+    pub fn set_mti(&mut self, mti: MTI) {
+        self.mti = mti;
+    }
+
+    pub fn bitmap(&self) -> &BitMap {
+        &self.bitmap
+    }
+
+    /// Spec of the message. The reference borrows the message, as messages
+    /// built with [`Message::new_owned`] or [`Message::from_bytes_owned`] own
+    /// their spec; see [`Message::borrowed_spec`] to keep the spec past the
+    /// message.
+    pub fn spec(&self) -> &MessageSpec {
+        &self.spec
+    }
+
+    /// Spec of a message built from a borrowed spec, for the full `'spec`
+    /// lifetime. `None` for messages sharing an `Arc` spec.
+    pub fn borrowed_spec(&self) -> Option<&'spec MessageSpec> {
+        match self.spec {
+            SpecRef::Borrowed(spec) => Some(spec),
+            SpecRef::Shared(_) => None,
+        }
+    }
+
+    /// True if no fields are set, as in some network management messages.
+    pub fn is_empty(&self) -> bool {
+        self.bitmap.is_empty()
+    }
+
     pub fn field(&self, id: usize) -> Option<&Field> {
-        if id >= self.fields.len() {
-            None
-        } else {
-            self.fields[id].as_ref()
+        self.fields.get(id)
+    }
+
+    /// Set fields with their indices, in ascending index order.
+    pub fn iter_fields(&self) -> impl Iterator<Item = (usize, &Field)> {
+        self.bitmap
+            .iter_set()
+            .filter_map(move |idx| self.fields.get(idx).map(|field| (idx, field)))
+    }
+
+    /// Field value masked according to its effective sensitivity. Fields
+    /// without a spec are masked entirely.
+    pub fn masked_field(&self, idx: usize) -> Option<Cow<'_, [u8]>> {
+        let field = self.fields.get(idx)?;
+        let sensitivity = self
+            .spec
+            .sensitivity(idx)
+            .unwrap_or(SensitivityType::MaskAll);
+        Some(sensitivity.mask(field.as_slice()))
+    }
+
+    /// Field `idx` wrapped for logging, masked according to its effective
+    /// sensitivity. Fields without a spec are masked entirely.
+    pub fn masked(&self, idx: usize) -> Option<MaskedField<'_>> {
+        let field = self.fields.get(idx)?;
+        let sensitivity = self
+            .spec
+            .sensitivity(idx)
+            .unwrap_or(SensitivityType::MaskAll);
+        Some(MaskedField::new(field, sensitivity))
+    }
+
+    /// Multi-line dump of the MTI and every set field, safe for logging:
+    /// field values are masked as by [`Message::masked_field`], and bytes
+    /// outside printable ASCII are escaped.
+    ///
+    /// ```text
+    /// MTI 0200
+    /// DE2 PAN: 411111******1111
+    /// DE3 PROCESSING CODE: 000000
+    /// ```
+    pub fn masked_display(&self) -> String {
+        let mut out = format!("MTI {}", String::from_utf8_lossy(self.mti.as_bytes()));
+        for idx in self.bitmap.iter_set() {
+            let value = match self.masked_field(idx) {
+                Some(value) => value,
+                None => continue,
+            };
+            let name = match self.spec.fields.get(idx) {
+                Some(Some(fs)) => format!(" {}", fs.name),
+                _ => String::new(),
+            };
+            out.push_str(&format!(
+                "\nDE{}{}: {}",
+                idx + 1,
+                name,
+                value.escape_ascii()
+            ));
+        }
+        out
+    }
+
+    /// Set field value, checking that the spec defines a field at this index
+    /// and that the value fits its length constraints.
+    pub fn set_field<T>(&mut self, idx: usize, value: T) -> Result<(), RS8583Error>
+    where
+        T: Into<Bytes>,
+    {
+        check_field_index(idx)?;
+        match self.spec.fields.get(idx) {
+            Some(Some(field_spec)) => {
+                let value = value.into();
+                field_spec.check_length(idx, value.len())?;
+                self.set_field_raw(idx, value)
+            }
+            _ => Err(RS8583Error::parse_error(format!(
+                "field {} not defined in spec",
+                idx
+            ))),
         }
     }
 
-    pub fn set_field<T>(&mut self, idx: usize, value: T)
+    /// Set field value without consulting the spec. The index must still be
+    /// a data bit no higher than [`MAX_FIELD_INDEX`].
+    ///
+    /// Fields without a spec cannot be serialized (see
+    /// `Codec::unspecified_fields`), so this is only useful when the spec is
+    /// known to be filled in later.
+    pub fn set_field_raw<T>(&mut self, idx: usize, value: T) -> Result<(), RS8583Error>
     where
         T: Into<Bytes>,
     {
-        // TODO: check max idx
-        // TODO: check value length (and possibly format)
-        self.fields[idx] = Some(Field::from_bytes(value.into()));
+        check_field_index(idx)?;
+        self.fields.set(idx, Field::from_bytes(value.into()));
         self.bitmap.set(idx);
+        Ok(())
+    }
+
+    /// Copy field `idx` from `source` verbatim, sharing its underlying bytes.
+    /// Does nothing if the field is absent in `source`.
+    pub fn echo_field(&mut self, source: &Message, idx: usize) -> Result<(), RS8583Error> {
+        if let Some(field) = source.field(idx) {
+            if let Some(Some(_)) = self.spec.fields.get(idx) {
+                self.fields.set(idx, field.clone());
+                self.bitmap.set(idx);
+            } else {
+                return Err(RS8583Error::parse_error(format!(
+                    "field {} not defined in spec",
+                    idx
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn echo_fields(&mut self, source: &Message, fields: &[usize]) -> Result<(), RS8583Error> {
+        for idx in fields {
+            self.echo_field(source, *idx)?;
+        }
+        Ok(())
     }
 
+    /// Remove field `idx`, dropping bitmap chunks left without fields.
     pub fn clear_field(&mut self, idx: usize) {
-        self.fields[idx] = None;
+        self.fields.remove(idx);
         self.bitmap.clear(idx);
+        self.bitmap.compact();
     }
 
-    pub fn serialize(&self, codec: &Codec) -> Result<BytesMut, RS8583Error> {
-        // TODO: compute capacity
-        let mut buf = BytesMut::with_capacity(32);
+    /// Store-and-forward advice for this authorization or financial request,
+    /// carrying all of its fields.
+    pub fn to_advice(&self) -> Result<Message<'spec>, RS8583Error> {
+        let mut advice = self.clone();
+        advice.mti = self.mti.to_advice()?;
+        Ok(advice)
+    }
 
-        // MTI
-        buf.put(self.mti.0.as_ref());
-        // BITMAP
-        self.bitmap.serialize(&mut buf);
-        // FIELDS
+    /// Compare MTI and field data with `other`, skipping the fields listed in
+    /// `ignore` (e.g. DE7 and DE11, which legitimately differ between a
+    /// request and its echo).
+    pub fn eq_ignoring(&self, other: &Message, ignore: &[usize]) -> bool {
+        if self.mti.as_bytes() != other.mti.as_bytes() {
+            return false;
+        }
+        self.bitmap
+            .iter_set()
+            .chain(other.bitmap.iter_set())
+            .filter(|idx| !ignore.contains(idx))
+            .all(|idx| {
+                self.field(idx).map(Field::as_slice) == other.field(idx).map(Field::as_slice)
+            })
+    }
+
+    /// Bitmap to put on the wire, applying `codec.unspecified_fields` to set
+    /// fields that the spec does not define.
+    fn wire_bitmap(&self, codec: &Codec) -> Result<Cow<'_, BitMap>, RS8583Error> {
+        let mut bitmap = Cow::Borrowed(&self.bitmap);
         for idx in self.bitmap.iter_set() {
-            if let Some(field) = self.field(idx) {
-                let field_spec = self.spec.fields.get(idx).unwrap();
-                if field_spec.is_none() {
-                    // WARN
-                    continue;
+            if let Some(Some(_)) = self.spec.fields.get(idx) {
+                continue;
+            }
+            match codec.unspecified_fields {
+                UnspecifiedField::Error => {
+                    return Err(RS8583Error::parse_error(format!(
+                        "field {} set but not defined in spec",
+                        idx
+                    )))
+                }
+                UnspecifiedField::Drop => bitmap.to_mut().clear(idx),
+            }
+        }
+        if let Cow::Owned(bitmap) = &mut bitmap {
+            bitmap.compact();
+        }
+        Ok(bitmap)
+    }
+
+    fn serialize_header(&self, codec: &Codec, bitmap: &BitMap) -> (BytesMut, BytesMut) {
+        let mut mti = BytesMut::with_capacity(4);
+        self.mti.serialize(codec, &mut mti);
+        let mut bitmap_buf = BytesMut::new();
+        bitmap.serialize(codec, &mut bitmap_buf);
+        match codec.header_order {
+            HeaderOrder::MTIFirst => (mti, bitmap_buf),
+            HeaderOrder::BitmapFirst => (bitmap_buf, mti),
+        }
+    }
+
+    /// Run the serialize hooks of the spec on this message.
+    pub fn apply_serialize_hooks(&mut self) -> Result<(), RS8583Error> {
+        let spec = self.spec.clone();
+        for hook in spec.serialize_hooks.iter() {
+            hook(self)?;
+        }
+        Ok(())
+    }
+
+    /// This message as it goes on the wire: a copy with the serialize hooks
+    /// applied, if the spec has any.
+    fn prepared(&self) -> Result<Cow<'_, Message<'spec>>, RS8583Error> {
+        if self.spec.serialize_hooks.is_empty() {
+            return Ok(Cow::Borrowed(self));
+        }
+        let mut msg = self.clone();
+        msg.apply_serialize_hooks()?;
+        Ok(Cow::Owned(msg))
+    }
+
+    /// Serialize the message, after running the serialize hooks of the spec
+    /// on a copy of it.
+    pub fn serialize(&self, codec: &Codec) -> Result<BytesMut, RS8583Error> {
+        self.prepared()?.serialize_into(codec, None)
+    }
+
+    /// Index and byte range, length prefix included, of every field in the
+    /// output of [`Message::serialize`].
+    pub fn field_layout(&self, codec: &Codec) -> Result<Vec<(usize, Range<usize>)>, RS8583Error> {
+        let mut layout = Vec::new();
+        self.prepared()?.serialize_into(codec, Some(&mut layout))?;
+        Ok(layout)
+    }
+
+    /// Field-aligned dump of the message as [`Message::serialize`] writes it:
+    /// MTI, bitmap with the list of present data elements, then one line per
+    /// field with its offset, DE number, name, wire length, wire bytes in hex
+    /// and value. Fields that are not `SensitivityType::Normal` have their
+    /// wire bytes hidden and their value masked.
+    ///
+    /// ```text
+    /// MTI    30 32 30 30
+    /// BITMAP 06 00 00 00 00 00 00 00 (DE2 DE3)
+    /// 000c DE2 PAN [18]: ** ** ** ** ** ** ** ** ** ** ** ** ** ** ** ** ** ** | 411111******1111
+    /// 001e DE3 PROCESSING CODE [6]: 30 30 30 30 30 30 | 000000
+    /// ```
+    pub fn hex_dump(&self, codec: &Codec) -> Result<String, RS8583Error> {
+        let hex = |data: &[u8]| {
+            data.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let msg = self.prepared()?;
+        let bitmap = msg.wire_bitmap(codec)?;
+        let mut mti = BytesMut::new();
+        msg.mti.serialize(codec, &mut mti);
+        let mut bitmap_buf = BytesMut::new();
+        bitmap.serialize(codec, &mut bitmap_buf);
+        let des: Vec<String> = bitmap.iter_des().map(|de| format!("DE{}", de)).collect();
+        let mut out = format!(
+            "MTI    {}\nBITMAP {} ({})",
+            hex(&mti),
+            hex(&bitmap_buf),
+            des.join(" ")
+        );
+
+        let mut layout = Vec::new();
+        let serialized = msg.serialize_into(codec, Some(&mut layout))?;
+        for (idx, range) in layout {
+            let wire = &serialized[range.clone()];
+            let (name, sensitivity) = match msg.spec.fields.get(idx) {
+                Some(Some(fs)) => (fs.name.as_str(), msg.spec.sensitivity(idx)),
+                _ => ("", None),
+            };
+            let wire_hex = match sensitivity {
+                Some(SensitivityType::Normal) => hex(wire),
+                _ => vec!["**"; wire.len()].join(" "),
+            };
+            let value = msg.masked_field(idx).unwrap_or_default();
+            out.push_str(&format!(
+                "\n{:04x} DE{} {} [{}]: {} | {}",
+                range.start,
+                idx + 1,
+                name,
+                wire.len(),
+                wire_hex,
+                value.escape_ascii()
+            ));
+        }
+        Ok(out)
+    }
+
+    /// Number of bytes [`Message::serialize`] would output, framing header
+    /// and LRC included.
+    pub fn serialized_len(&self, codec: &Codec) -> Result<usize, RS8583Error> {
+        let msg = self.prepared()?;
+        let bitmap = msg.wire_bitmap(codec)?;
+        Ok(codec.frame_header_len() + msg.body_len(codec, &bitmap, &[]) + usize::from(codec.lrc))
+    }
+
+    /// Bytes to compute a MAC or signature over: MTI, bitmap and fields in
+    /// wire order, as [`Message::serialize`] would emit them but without
+    /// framing header or LRC. Fields listed in `exclude` (typically DE64 or
+    /// DE128, indices 63 and 127) are left out of the data, while their
+    /// bitmap bits stay as they are, so the MAC covers the presence of the
+    /// MAC field. Serialize hooks are not run; apply them first for the MAC
+    /// to match the message sent.
+    pub fn canonical_bytes(
+        &self,
+        codec: &Codec,
+        exclude: &[usize],
+    ) -> Result<BytesMut, RS8583Error> {
+        let bitmap = self.wire_bitmap(codec)?;
+        let mut buf = BytesMut::with_capacity(self.body_len(codec, &bitmap, exclude));
+        self.serialize_body(codec, &bitmap, exclude, &mut buf, None)?;
+        Ok(buf)
+    }
+
+    /// Length of the MTI, bitmap and fields on the wire.
+    fn body_len(&self, codec: &Codec, bitmap: &BitMap, exclude: &[usize]) -> usize {
+        let mti_len = match codec.mti_encoding {
+            MTIEncoding::BCD => 2,
+            MTIEncoding::ASCII | MTIEncoding::EBCDIC => 4,
+        };
+        let fields_len: usize = bitmap
+            .iter_set()
+            .filter(|idx| !exclude.contains(idx))
+            .filter_map(|idx| match (self.field(idx), self.spec.fields.get(idx)) {
+                (Some(field), Some(Some(field_spec))) => {
+                    Some(field_spec.serialized_len(codec, field))
                 }
-                let field_spec = field_spec.as_ref().unwrap();
-                field_spec.serialize_field(codec, &mut buf, field)?;
+                _ => None,
+            })
+            .sum();
+        mti_len + bitmap.serialized_len(codec) + fields_len
+    }
+
+    fn serialize_body(
+        &self,
+        codec: &Codec,
+        bitmap: &BitMap,
+        exclude: &[usize],
+        buf: &mut BytesMut,
+        mut layout: Option<&mut Vec<(usize, Range<usize>)>>,
+    ) -> Result<(), RS8583Error> {
+        // MTI + BITMAP
+        let (first, second) = self.serialize_header(codec, bitmap);
+        buf.extend_from_slice(&first);
+        buf.extend_from_slice(&second);
+        // FIELDS
+        for idx in codec.field_order.order(bitmap) {
+            if exclude.contains(&idx) {
+                continue;
+            }
+            let start = buf.len();
+            self.serialize_field_at(codec, buf, idx)?;
+            if let Some(layout) = layout.as_mut() {
+                layout.push((idx, start..buf.len()));
             }
         }
+        Ok(())
+    }
 
+    fn serialize_into(
+        &self,
+        codec: &Codec,
+        layout: Option<&mut Vec<(usize, Range<usize>)>>,
+    ) -> Result<BytesMut, RS8583Error> {
+        let bitmap = self.wire_bitmap(codec)?;
+        let header_len = codec.frame_header_len();
+        let mut buf = BytesMut::with_capacity(
+            header_len + self.body_len(codec, &bitmap, &[]) + usize::from(codec.lrc),
+        );
+        // The frame header is filled in once the actual length is known
+        buf.resize(header_len, 0);
+        self.serialize_body(codec, &bitmap, &[], &mut buf, layout)?;
+        if codec.lrc {
+            buf.put_u8(codec::lrc(&buf[header_len..]));
+        }
+        if header_len > 0 {
+            let mut header = BytesMut::with_capacity(header_len);
+            codec.serialize_frame_header(&mut header, buf.len() - header_len)?;
+            buf[..header_len].copy_from_slice(&header);
+        }
         Ok(buf)
     }
+
+    /// Serialize the message as a sequence of chunks: MTI, bitmap, then one
+    /// chunk per field (length prefix and data). Concatenating all chunks
+    /// gives the same bytes as [`Message::serialize`], minus the framing
+    /// header, without ever holding the whole message in a single buffer.
+    /// Serialize hooks are not run; see [`Message::apply_serialize_hooks`].
+    pub fn serialize_chunks<'a>(
+        &'a self,
+        codec: &'a Codec,
+    ) -> impl Iterator<Item = Result<Bytes, RS8583Error>> + 'a {
+        let (header, order) = match self.wire_bitmap(codec) {
+            Ok(bitmap) => {
+                let (first, second) = self.serialize_header(codec, &bitmap);
+                (
+                    vec![Ok(first.freeze()), Ok(second.freeze())],
+                    codec.field_order.order(&bitmap),
+                )
+            }
+            Err(e) => (vec![Err(e)], Vec::new()),
+        };
+
+        let fields = order.into_iter().filter_map(move |idx| {
+            let mut buf = BytesMut::new();
+            match self.serialize_field_at(codec, &mut buf, idx) {
+                Ok(()) if buf.is_empty() => None,
+                Ok(()) => Some(Ok(buf.freeze())),
+                Err(e) => Some(Err(e)),
+            }
+        });
+        // A trailing `None` marks the end, where the LRC chunk is emitted
+        header
+            .into_iter()
+            .map(Some)
+            .chain(fields.map(Some))
+            .chain(std::iter::once(None))
+            .scan(0u8, move |acc, chunk| match chunk {
+                Some(Ok(chunk)) => {
+                    *acc ^= codec::lrc(&chunk);
+                    Some(Some(Ok(chunk)))
+                }
+                Some(Err(e)) => Some(Some(Err(e))),
+                None if codec.lrc => Some(Some(Ok(Bytes::from(vec![*acc])))),
+                None => Some(None),
+            })
+            .flatten()
+    }
+
+    /// Serialize the message and write it to `writer`, framing header
+    /// included. Unframed messages are written chunk by chunk as they are
+    /// produced; framed ones are collected first, as the header needs the
+    /// total length.
+    #[cfg(feature = "async")]
+    pub async fn write_to<W: AsyncWrite + Unpin>(
+        &self,
+        codec: &Codec,
+        writer: &mut W,
+    ) -> std::io::Result<()> {
+        let invalid = |e: RS8583Error| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let msg = self.prepared().map_err(invalid)?;
+        if let Framing::Unframed = codec.framing {
+            for chunk in msg.serialize_chunks(codec) {
+                writer.write_all(&chunk.map_err(invalid)?).await?;
+            }
+            return Ok(());
+        }
+        let chunks = msg
+            .serialize_chunks(codec)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let mut header = BytesMut::new();
+        codec
+            .serialize_frame_header(&mut header, chunks.iter().map(Bytes::len).sum())
+            .map_err(invalid)?;
+        writer.write_all(&header).await?;
+        for chunk in chunks {
+            writer.write_all(&chunk).await?;
+        }
+        Ok(())
+    }
+
+    fn serialize_field_at(
+        &self,
+        codec: &Codec,
+        buf: &mut BytesMut,
+        idx: usize,
+    ) -> Result<(), RS8583Error> {
+        // Fields without a spec have been dealt with by `wire_bitmap`
+        if let (Some(field), Some(Some(field_spec))) = (self.field(idx), self.spec.fields.get(idx))
+        {
+            field_spec
+                .serialize_field(codec, buf, field)
+                .map_err(|e| RS8583Error::field_parse_error(idx, &field_spec.name, e))?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::codec::{BitmapEncoding, Encoding, FieldOrder, HeaderOrder, MTIEncoding};
     use crate::spec::*;
 
     fn test_spec() -> MessageSpec {
@@ -285,6 +1120,7 @@ mod tests {
                     length_type: LengthType::Fixed,
                     sensitivity: SensitivityType::Normal,
                     length: 12,
+                    ..FieldSpec::default()
                 }),
                 Some(FieldSpec {
                     name: String::from("TEST FIELD 3"),
@@ -292,6 +1128,7 @@ mod tests {
                     length_type: LengthType::Fixed,
                     sensitivity: SensitivityType::Normal,
                     length: 4,
+                    ..FieldSpec::default()
                 }),
                 None,
                 Some(FieldSpec {
@@ -300,6 +1137,7 @@ mod tests {
                     length_type: LengthType::Fixed,
                     sensitivity: SensitivityType::Normal,
                     length: 2,
+                    ..FieldSpec::default()
                 }),
                 None,
                 Some(FieldSpec {
@@ -308,6 +1146,7 @@ mod tests {
                     length_type: LengthType::LLVar,
                     sensitivity: SensitivityType::Normal,
                     length: 20,
+                    ..FieldSpec::default()
                 }),
                 Some(FieldSpec {
                     name: String::from("TEST FIELD 7"),
@@ -315,8 +1154,10 @@ mod tests {
                     length_type: LengthType::Fixed,
                     sensitivity: SensitivityType::Normal,
                     length: 4,
+                    ..FieldSpec::default()
                 }),
             ],
+            ..MessageSpec::default()
         }
     }
 
@@ -337,18 +1178,22 @@ mod tests {
         assert!(mti.is_from_acquirer());
         assert!(!mti.is_repeat());
 
-        assert_eq!(msg.bitmap.test(0), false);
-        assert_eq!(msg.bitmap.test(1), true);
-        assert_eq!(msg.bitmap.test(2), true);
-        assert_eq!(msg.bitmap.test(3), false);
-        assert_eq!(msg.bitmap.test(4), true);
-        assert_eq!(msg.bitmap.test(5), false);
-        assert_eq!(msg.bitmap.test(6), true);
-        assert_eq!(msg.bitmap.test(7), false);
-        assert_eq!(msg.bitmap.test(63), false);
-
-        assert!(msg.fields[0].is_none());
-        assert!(msg.fields[1].is_some());
+        assert!(!msg.bitmap.test(0));
+        assert!(msg.bitmap.test(1));
+        assert!(msg.bitmap.test(2));
+        assert!(!msg.bitmap.test(3));
+        assert!(msg.bitmap.test(4));
+        assert!(!msg.bitmap.test(5));
+        assert!(msg.bitmap.test(6));
+        assert!(!msg.bitmap.test(7));
+        assert!(!msg.bitmap.test(63));
+
+        assert!(msg.fields.get(0).is_none());
+        assert!(msg.fields.get(1).is_some());
+        assert_eq!(
+            msg.iter_fields().map(|(idx, _)| idx).collect::<Vec<_>>(),
+            vec![1, 2, 4, 6]
+        );
 
         let fld = msg.field(1).unwrap();
         assert_eq!(fld.as_slice(), b"111122223333");
@@ -379,12 +1224,12 @@ mod tests {
         assert_eq!(serialized.as_ref(), &orig_raw[..]);
         assert_eq!(serialized.as_ref(), &orig_raw[..]);
 
-        msg.set_field(7, "1234");
+        msg.set_field(7, "1234")?;
 
         let fld = msg.field(7).unwrap();
         assert_eq!(fld.as_slice(), b"1234");
         assert_eq!(fld.len(), 4);
-        assert_eq!(msg.bitmap.test(7), true);
+        assert!(msg.bitmap.test(7));
 
         let serialized = msg.serialize(&codec).unwrap();
         assert_eq!(
@@ -396,4 +1241,1295 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn message_tertiary_bitmap() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut fields: Vec<Option<FieldSpec>> = (0..192).map(|_| None).collect();
+        fields[2] = Some(FieldSpec {
+            name: String::from("PROCESSING CODE"),
+            field_type: FieldType::N,
+            length: 6,
+            ..FieldSpec::default()
+        });
+        fields[130] = Some(FieldSpec {
+            name: String::from("NATIONAL USE"),
+            length_type: LengthType::LLVar,
+            length: 20,
+            ..FieldSpec::default()
+        });
+        let spec = MessageSpec {
+            fields,
+            ..MessageSpec::default()
+        };
+        let raw = b"0200\x05\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\
+            \x04\x00\x00\x00\x00\x00\x00\x00\
+            00000008TERTIARY"
+            .to_vec();
+        let orig_raw = raw.clone();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.bitmap().iter_des().collect::<Vec<_>>(), vec![3, 131]);
+        assert_eq!(msg.field(130).unwrap().as_slice(), b"TERTIARY");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        msg.set_field(130, "NATIONAL")?;
+        assert_eq!(msg.field(130).unwrap().as_slice(), b"NATIONAL");
+
+        let mut msg = Message::new(&spec);
+        msg.set_field(130, "NEW")?;
+        assert_eq!(msg.bitmap().num_chunks(), 3);
+        assert_eq!(
+            msg.serialize(&codec)?.as_ref(),
+            &b"0000\x01\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\
+               \x04\x00\x00\x00\x00\x00\x00\x0003NEW"[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_set_undefined_field() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(
+            msg.set_field(5, "12"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 5 not defined in spec"),
+            })
+        );
+        assert_eq!(
+            msg.set_field(37, "12"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 37 not defined in spec"),
+            })
+        );
+        assert_eq!(
+            msg.set_field(200, "12"),
+            Err(RS8583Error::parse_error("field 200 out of range (max 191)"))
+        );
+        assert_eq!(
+            msg.set_field_raw(usize::MAX, "12"),
+            Err(RS8583Error::parse_error(format!(
+                "field {} out of range (max 191)",
+                usize::MAX
+            )))
+        );
+        assert_eq!(
+            msg.set_field_raw(64, "12"),
+            Err(RS8583Error::parse_error(
+                "field 64 is a bitmap continuation bit"
+            ))
+        );
+        assert!(msg.field(5).is_none());
+        assert!(!msg.bitmap.test(5));
+
+        msg.set_field_raw(5, "12")?;
+        assert_eq!(msg.field(5).unwrap().as_slice(), b"12");
+        assert!(msg.bitmap.test(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_serialize_unspecified_field() -> Result<(), RS8583Error> {
+        let mut codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0120\x14\x00\x00\x00\x00\x00\x00\x00ABCDXY".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw.clone()))?;
+        msg.set_field_raw(5, "12")?;
+
+        let err = || RS8583Error::ParseError {
+            error: String::from("field 5 set but not defined in spec"),
+        };
+        assert_eq!(msg.serialize(&codec).err(), Some(err()));
+        assert_eq!(
+            msg.serialize_chunks(&codec).collect::<Result<Vec<_>, _>>(),
+            Err(err())
+        );
+
+        codec.unspecified_fields = UnspecifiedField::Drop;
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &raw[..]);
+        let chunks = msg
+            .serialize_chunks(&codec)
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        assert_eq!(chunks, raw);
+        assert!(msg.bitmap.test(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_custom_field_order() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            field_order: FieldOrder::Custom(vec![6, 4]),
+            ..Codec::default()
+        };
+        let spec = test_spec();
+        let raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x0005LLVARXY111122223333ABCD".to_vec();
+        let orig_raw = raw.clone();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.field(1).unwrap().as_slice(), b"111122223333");
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"ABCD");
+        assert_eq!(msg.field(4).unwrap().as_slice(), b"XY");
+        assert_eq!(msg.field(6).unwrap().as_slice(), b"LLVAR");
+
+        let serialized = msg.serialize(&codec)?;
+        assert_eq!(serialized.as_ref(), &orig_raw[..]);
+
+        let serialized = msg.serialize(&Codec::default())?;
+        assert_eq!(
+            serialized.as_ref(),
+            &b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR"[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_hex_bitmap() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            bitmap_encoding: BitmapEncoding::ASCIIHex,
+            ..Codec::default()
+        };
+        let spec = test_spec();
+        let raw = b"0120d600000000000000111122223333ABCDXY05LLVAR\x01\x02\xff\x00".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert!(msg.bitmap.test(1));
+        assert!(msg.bitmap.test(7));
+        assert!(!msg.bitmap.test(3));
+        assert_eq!(msg.field(7).unwrap().as_slice(), b"\x01\x02\xff\x00");
+
+        let serialized = msg.serialize(&codec)?;
+        assert_eq!(
+            serialized.as_ref(),
+            &b"0120D600000000000000111122223333ABCDXY05LLVAR\x01\x02\xff\x00"[..]
+        );
+
+        let raw = b"0120D60000000000000G".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Invalid bitmap hex digit: 0x47"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_filler() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        spec.fields[1].as_mut().unwrap().filler = 2;
+        let raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333\x00\x00ABCDXY05LLVAR".to_vec();
+        let orig_raw = raw.clone();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.field(1).unwrap().as_slice(), b"111122223333");
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"ABCD");
+        assert_eq!(msg.field(6).unwrap().as_slice(), b"LLVAR");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        let raw = b"0120\x02\x00\x00\x00\x00\x00\x00\x00111122223333\x00".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::field_parse_error(
+                1,
+                "TEST FIELD 2",
+                RS8583Error::parse_error("Truncated filler"),
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_bcd_field() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        {
+            let fs = spec.fields[4].as_mut().unwrap();
+            fs.field_type = FieldType::N;
+            fs.length = 4;
+            fs.bcd = true;
+        }
+        let raw = b"0120\x10\x00\x00\x00\x00\x00\x00\x00\x12\x34".to_vec();
+        let orig_raw = raw.clone();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.field(4).unwrap().as_slice(), b"1234");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        let raw = b"0120\x10\x00\x00\x00\x00\x00\x00\x00\x12\x3f".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::field_parse_error(
+                4,
+                "TEST FIELD 5",
+                RS8583Error::parse_error("Invalid BCD byte: 0x3f"),
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_ebcdic_data() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            data_encoding: Encoding::EBCDIC,
+            ..Codec::default()
+        };
+        let mut spec = test_spec();
+        spec.fields[2].as_mut().unwrap().length = 5;
+        let raw =
+            b"0120\x84\x00\x00\x00\x00\x00\x00\x00\xc8\xc5\xd3\xd3\xd6\xc8\xc5\x00\x01".to_vec();
+        let orig_raw = raw.clone();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"HELLO");
+        assert_eq!(msg.field(7).unwrap().as_slice(), b"\xc8\xc5\x00\x01");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        msg.set_field(2, "WORLD")?;
+        assert_eq!(&msg.serialize(&codec)?[12..17], b"\xe6\xd6\xd9\xd3\xc4");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_lrc() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            lrc: true,
+            ..Codec::default()
+        };
+        let spec = test_spec();
+        let mut raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        raw.push(crate::codec::lrc(&raw));
+        let orig_raw = raw.clone();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw.clone()))?;
+
+        assert_eq!(msg.field(6).unwrap().as_slice(), b"LLVAR");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+        let chunks: Vec<u8> = msg
+            .serialize_chunks(&codec)
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        assert_eq!(chunks, orig_raw);
+
+        raw[20] ^= 0x01;
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("LRC mismatch"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn message_write_to() -> Result<(), RS8583Error> {
+        use crate::codec::Framing;
+
+        let codec = Codec {
+            framing: Framing::MHeader,
+            ..Codec::default()
+        };
+        let spec = test_spec();
+        let raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let msg = Message::from_bytes(&spec, &Codec::default(), Bytes::from(raw.clone()))?;
+
+        let mut out: Vec<u8> = Vec::new();
+        msg.write_to(&codec, &mut out).await.unwrap();
+        assert_eq!(&out[..2], &[0x00, raw.len() as u8][..]);
+        assert_eq!(&out[2..], &raw[..]);
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &out[..]);
+
+        let read_back = Message::from_bytes(&spec, &codec, Bytes::from(out))?;
+        assert_eq!(read_back.field(6).unwrap().as_slice(), b"LLVAR");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_de1_fixed_spec() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        spec.fields.resize_with(128, || None);
+        spec.fields[0] = Some(FieldSpec {
+            name: String::from("SECONDARY BITMAP"),
+            field_type: FieldType::B,
+            length: 8,
+            ..FieldSpec::default()
+        });
+        spec.fields[65] = Some(FieldSpec {
+            name: String::from("SETTLEMENT CODE"),
+            field_type: FieldType::N,
+            length: 1,
+            ..FieldSpec::default()
+        });
+        let raw =
+            b"0120\x03\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x001111222233331"
+                .to_vec();
+        let orig_raw = raw.clone();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert!(msg.field(0).is_none());
+        assert_eq!(msg.field(1).unwrap().as_slice(), b"111122223333");
+        assert_eq!(msg.field(65).unwrap().as_slice(), b"1");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        assert_eq!(
+            msg.set_field(0, "12345678"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 0 is a bitmap continuation bit"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_empty() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0800\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw.clone()))?;
+
+        assert!(msg.is_empty());
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &raw[..]);
+
+        msg.set_field(2, "ABCD")?;
+        assert!(!msg.is_empty());
+        msg.clear_field(2);
+        assert!(msg.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_field_dependencies() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        spec.add_dependency(2, 7);
+        spec.add_group(&[1, 4]);
+        let raw = b"0120\x52\x00\x00\x00\x00\x00\x00\x00111122223333XY05LLVAR".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.validate(), Ok(()));
+
+        msg.set_field(2, "ABCD")?;
+        msg.clear_field(4);
+        assert_eq!(
+            msg.validate(),
+            Err(vec![
+                RS8583Error::ParseError {
+                    error: String::from("DE3 present but DE8 missing"),
+                },
+                RS8583Error::ParseError {
+                    error: String::from("DE2 present but DE5 missing"),
+                },
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_serialized_len() -> Result<(), RS8583Error> {
+        let mut spec = test_spec();
+        spec.fields[4].as_mut().unwrap().bcd = true;
+        spec.fields[4].as_mut().unwrap().field_type = FieldType::N;
+        spec.fields[6].as_mut().unwrap().filler = 3;
+        let mut msg = Message::new(&spec);
+        msg.set_field(1, "111122223333")?;
+        msg.set_field(4, "12")?;
+        msg.set_field(6, "LLVAR")?;
+        msg.set_field(7, "\x00\x01\x02\x03")?;
+
+        let codecs = [
+            Codec::default(),
+            Codec {
+                framing: Framing::MHeader,
+                lrc: true,
+                ..Codec::default()
+            },
+            Codec {
+                framing: Framing::VHeader {
+                    width: 4,
+                    ascii: true,
+                },
+                mti_encoding: MTIEncoding::BCD,
+                bitmap_encoding: BitmapEncoding::ASCIIHex,
+                ll_format: crate::codec::VariableLengthFormat::Byte,
+                ..Codec::default()
+            },
+        ];
+        for codec in codecs.iter() {
+            let serialized = msg.serialize(codec)?;
+            assert_eq!(msg.serialized_len(codec)?, serialized.len());
+            assert_eq!(serialized.capacity(), serialized.len());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_owned_spec() -> Result<(), RS8583Error> {
+        fn decode(raw: &'static [u8]) -> Result<Message<'static>, RS8583Error> {
+            let spec = Arc::new(test_spec());
+            Message::from_bytes_owned(spec, &Codec::default(), Bytes::from_static(raw))
+        }
+
+        let mut queue = Vec::new();
+        queue.push(decode(b"0120\x04\x00\x00\x00\x00\x00\x00\x00ABCD")?);
+        queue.push(decode(b"0130\x04\x00\x00\x00\x00\x00\x00\x00EFGH")?);
+        assert_eq!(queue[0].field(2).unwrap().as_slice(), b"ABCD");
+        assert_eq!(queue[1].mti().as_bytes(), b"0130");
+        assert_eq!(
+            queue[1].serialize(&Codec::default())?.as_ref(),
+            b"0130\x04\x00\x00\x00\x00\x00\x00\x00EFGH"
+        );
+
+        let mut msg = Message::new_owned(Arc::new(test_spec()));
+        msg.set_field(2, "WXYZ")?;
+        queue.push(msg);
+        assert_eq!(queue[2].spec().fields.len(), 8);
+        assert!(queue[2].borrowed_spec().is_none());
+
+        let spec = test_spec();
+        let msg_spec = {
+            let msg = Message::new(&spec);
+            msg.borrowed_spec().unwrap()
+        };
+        assert!(std::ptr::eq(msg_spec, &spec));
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_undefined_field_bit() {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0120\x4a\x00\x00\x00\x00\x00\x00\x00111122223333XX05LLVAR".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::parse_error(
+                "field 3 set but not defined in spec, cannot determine its length"
+            ))
+        );
+
+        let raw = b"0120\x00\x00\x00\x00\x00\x00\x20\x00".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::parse_error(
+                "field 53 set but not defined in spec, cannot determine its length"
+            ))
+        );
+    }
+
+    #[test]
+    fn message_validate_fields() -> Result<(), RS8583Error> {
+        let mut spec = test_spec();
+        spec.fields[2].as_mut().unwrap().field_type = FieldType::N;
+        let mut msg = Message::new(&spec);
+        msg.set_field(1, "111122223333")?;
+        msg.set_field(2, "12A4")?;
+        msg.set_field_raw(6, "LONGER THAN TWENTY CHARS")?;
+        msg.set_field_raw(3, "??")?;
+        assert_eq!(
+            msg.validate(),
+            Err(vec![
+                RS8583Error::parse_error("field 2: invalid character 0x41 for type N"),
+                RS8583Error::parse_error("field 3 not defined in spec"),
+                RS8583Error::parse_error("field 6: length 24 above max 20"),
+            ])
+        );
+
+        msg.set_field(2, "1234")?;
+        msg.set_field(6, "SHORT")?;
+        msg.clear_field(3);
+        assert_eq!(msg.validate(), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_presence_policy() -> Result<(), RS8583Error> {
+        let mut registry = SpecRegistry::new();
+        registry.register(b"0200", test_spec());
+        registry.set_presence_policy(
+            b"0200",
+            PresencePolicy {
+                mandatory: vec![1, 4],
+                optional: vec![2],
+                conditional: vec![6],
+            },
+        )?;
+        assert!(registry
+            .set_presence_policy(b"0800", PresencePolicy::default())
+            .is_err());
+
+        let spec = registry.resolve(&MTI(*b"0200")).unwrap();
+        let mut msg = Message::with_mti(spec, MTI(*b"0200"));
+        msg.set_field(1, "111122223333")?;
+        msg.set_field(4, "XY")?;
+        assert_eq!(msg.validate(), Ok(()));
+
+        msg.clear_field(4);
+        msg.set_field(7, "\x00\x01\x02\x03")?;
+        assert_eq!(
+            msg.validate(),
+            Err(vec![
+                RS8583Error::ParseError {
+                    error: String::from("DE5 mandatory for MTI 0200 but missing"),
+                },
+                RS8583Error::ParseError {
+                    error: String::from("DE8 not allowed for MTI 0200"),
+                },
+            ])
+        );
+
+        msg.set_mti(MTI(*b"0220"));
+        assert_eq!(msg.validate(), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_space_padded_mti() -> Result<(), RS8583Error> {
+        let mut codec = Codec::default();
+        let spec = test_spec();
+        let raw = b" 800\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw.clone())).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("MTI contains non-digit 0x20"),
+            })
+        );
+
+        codec.lenient_mti = true;
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.mti().as_bytes(), b"0800");
+        assert!(msg.mti().is_management());
+        let raw = b"800 \x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.mti().as_bytes(), b"0800");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_field_layout() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        let serialized = msg.serialize(&codec)?;
+        let layout = msg.field_layout(&codec)?;
+
+        assert_eq!(
+            layout,
+            vec![(1, 12..24), (2, 24..28), (4, 28..30), (6, 30..37)]
+        );
+        assert_eq!(layout[0].1.start, 12);
+        for pair in layout.windows(2) {
+            assert_eq!(pair[0].1.end, pair[1].1.start);
+        }
+        assert_eq!(layout.last().unwrap().1.end, serialized.len());
+        assert_eq!(&serialized[layout[3].1.clone()], b"05LLVAR");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_hex_dump() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            framing: Framing::MHeader,
+            ..Codec::default()
+        };
+        let mut spec = test_spec();
+        spec.fields[1].as_mut().unwrap().sensitivity = SensitivityType::MaskPAN;
+        let raw = b"\x00\x250120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(
+            msg.hex_dump(&codec)?,
+            concat!(
+                "MTI    30 31 32 30\n",
+                "BITMAP 56 00 00 00 00 00 00 00 (DE2 DE3 DE5 DE7)\n",
+                "000e DE2 TEST FIELD 2 [12]: ** ** ** ** ** ** ** ** ** ** ** ** | 111122**3333\n",
+                "001a DE3 TEST FIELD 3 [4]: 41 42 43 44 | ABCD\n",
+                "001e DE5 TEST FIELD 5 [2]: 58 59 | XY\n",
+                "0020 DE7 TEST FIELD 6 [7]: 30 35 4c 4c 56 41 52 | LLVAR",
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_strict_field_types() -> Result<(), RS8583Error> {
+        let mut codec = Codec::default();
+        let mut spec = test_spec();
+        {
+            let fs = spec.fields[2].as_mut().unwrap();
+            fs.name = String::from("PROCESSING CODE");
+            fs.field_type = FieldType::N;
+        }
+        let raw = b"0200\x04\x00\x00\x00\x00\x00\x00\x0000A0".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw.clone()))?;
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"00A0");
+
+        codec.strict_field_validation = true;
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw.clone())).err(),
+            Some(RS8583Error::field_parse_error(
+                2,
+                "PROCESSING CODE",
+                RS8583Error::parse_error("invalid character 0x41 for type N"),
+            ))
+        );
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw))
+                .err()
+                .unwrap()
+                .to_string(),
+            "field 2 (PROCESSING CODE): ISO8583 parse error: invalid character 0x41 for type N"
+        );
+        let raw = b"0200\x04\x00\x00\x00\x00\x00\x00\x000000".to_vec();
+        assert!(Message::from_bytes(&spec, &codec, Bytes::from(raw)).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_bitmap_first() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            header_order: HeaderOrder::BitmapFirst,
+            ..Codec::default()
+        };
+        let spec = test_spec();
+        let raw = b"\x56\x00\x00\x00\x00\x00\x00\x000120111122223333ABCDXY05LLVAR".to_vec();
+        let orig_raw = raw.clone();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(&msg.mti().0, b"0120");
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"ABCD");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_min_length() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        spec.fields[6].as_mut().unwrap().min_length = 2;
+
+        let raw = b"0120\x40\x00\x00\x00\x00\x00\x00\x0001X".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::field_parse_error(
+                6,
+                "TEST FIELD 6",
+                RS8583Error::parse_error("length 1 below min 2"),
+            ))
+        );
+
+        let raw = b"0120\x40\x00\x00\x00\x00\x00\x00\x0002XY".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(
+            msg.set_field(6, "Z"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 6: length 1 below min 2"),
+            })
+        );
+        assert_eq!(msg.field(6).unwrap().as_slice(), b"XY");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_set_field_length() -> Result<(), RS8583Error> {
+        let spec = test_spec();
+        let mut msg = Message::new(&spec);
+        assert_eq!(
+            msg.set_field(1, "123"),
+            Err(RS8583Error::parse_error(
+                "field 1: length 3 differs from fixed length 12"
+            ))
+        );
+        assert_eq!(
+            msg.set_field(6, "LONGER THAN TWENTY CHARS"),
+            Err(RS8583Error::parse_error("field 6: length 24 above max 20"))
+        );
+        assert!(msg.field(1).is_none());
+        assert!(msg.field(6).is_none());
+
+        msg.set_field(1, "111122223333")?;
+        msg.set_field(6, "SHORT")?;
+        assert_eq!(msg.field(6).unwrap().as_slice(), b"SHORT");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_echo_fields() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        spec.fields.resize_with(128, || None);
+        spec.fields[62] = Some(FieldSpec {
+            name: String::from("PRIVATE USE"),
+            field_type: FieldType::ANS,
+            length_type: LengthType::LLLVar,
+            length: 999,
+            ..FieldSpec::default()
+        });
+        let raw = b"0100\x02\x00\x00\x00\x00\x00\x00\x40111122223333008 \x00AB\xc1  \x00".to_vec();
+        let request = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        let raw = b"0110\x04\x00\x00\x00\x00\x00\x00\x00ABCD".to_vec();
+        let mut response = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        response.echo_fields(&request, &[1, 5, 62])?;
+        assert!(response.field(5).is_none());
+        for idx in &[1, 62] {
+            let orig = request.field(*idx).unwrap().as_slice();
+            let echoed = response.field(*idx).unwrap().as_slice();
+            assert_eq!(echoed, orig);
+            assert_eq!(echoed.as_ptr(), orig.as_ptr());
+        }
+        assert_eq!(
+            response.serialize(&codec)?.as_ref(),
+            &b"0110\x06\x00\x00\x00\x00\x00\x00\x40111122223333ABCD008 \x00AB\xc1  \x00"[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_eq_ignoring() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let first = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        let mut second = first.clone();
+        assert!(first.eq_ignoring(&second, &[]));
+
+        second.set_field(6, "OTHER")?;
+        assert!(!first.eq_ignoring(&second, &[]));
+        assert!(first.eq_ignoring(&second, &[6]));
+
+        second.clear_field(4);
+        assert!(!first.eq_ignoring(&second, &[6]));
+        assert!(second.eq_ignoring(&first, &[4, 6]));
+
+        second.set_mti(MTI(*b"0130"));
+        assert!(!first.eq_ignoring(&second, &[4, 6]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_to_advice() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0100\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let request = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        let advice = request.to_advice()?;
+
+        assert_eq!(advice.mti().as_bytes(), b"0120");
+        assert!(!advice.eq_ignoring(&request, &[]));
+        assert_eq!(
+            advice.serialize(&codec)?.as_ref(),
+            &b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR"[..]
+        );
+
+        assert_eq!(MTI(*b"0201").to_advice()?.as_bytes(), b"0221");
+        assert_eq!(
+            advice.to_advice().err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("MTI 0120 is not an authorization or financial request"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_faithful_round_trip() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            faithful: true,
+            ..Codec::default()
+        };
+        let mut spec = test_spec();
+        spec.fields[1].as_mut().unwrap().filler = 2;
+        let raw = b"0210\x56\x00\x00\x00\x00\x00\x00\x00111122223333  ABCDXY05LLVAR".to_vec();
+        let orig_raw = raw.clone();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        assert_eq!(msg.field(1).unwrap().as_slice(), b"111122223333");
+        assert_eq!(msg.field(1).unwrap().raw(), Some(&b"111122223333  "[..]));
+        assert_eq!(msg.field(6).unwrap().raw(), Some(&b"05LLVAR"[..]));
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+        assert_eq!(
+            msg.serialize(&Codec::default())?.as_ref(),
+            &b"0210\x56\x00\x00\x00\x00\x00\x00\x00111122223333\x00\x00ABCDXY05LLVAR"[..]
+        );
+
+        msg.set_field(1, "444455556666")?;
+        assert_eq!(
+            msg.serialize(&codec)?.as_ref(),
+            &b"0210\x56\x00\x00\x00\x00\x00\x00\x00444455556666\x00\x00ABCDXY05LLVAR"[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_reinterpret() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut registry = SpecRegistry::new();
+        registry.register(b"0200", test_spec());
+        let mut reversal_spec = test_spec();
+        reversal_spec.fields[6].as_mut().unwrap().length = 10;
+        reversal_spec.fields[6].as_mut().unwrap().name = String::from("REVERSAL FIELD 6");
+        registry.register(b"0420", reversal_spec);
+        let mut narrow_spec = test_spec();
+        narrow_spec.fields[6].as_mut().unwrap().length = 4;
+        registry.register(b"0400", narrow_spec);
+        let mut numeric_spec = test_spec();
+        numeric_spec.fields[2].as_mut().unwrap().field_type = FieldType::N;
+        registry.register(b"0220", numeric_spec);
+        let mut validated_spec = test_spec();
+        validated_spec.add_validator(4, |f| match f.as_slice() {
+            b"XY" => Err(String::from("XY not accepted")),
+            _ => Ok(()),
+        });
+        registry.register(b"0320", validated_spec);
+
+        let spec = registry.resolve(&MTI(*b"0200")).unwrap();
+        let raw = b"0200\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let mut msg = Message::from_bytes(spec, &codec, Bytes::from(raw))?;
+
+        msg.set_mti(MTI(*b"0420"));
+        msg.reinterpret(&registry)?;
+        assert_eq!(
+            msg.spec().fields[6].as_ref().unwrap().name,
+            "REVERSAL FIELD 6"
+        );
+        assert_eq!(msg.field(6).unwrap().as_slice(), b"LLVAR");
+
+        msg.set_mti(MTI(*b"0400"));
+        assert_eq!(
+            msg.reinterpret(&registry),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 6: length 5 above max 4"),
+            })
+        );
+        assert_eq!(
+            msg.spec().fields[6].as_ref().unwrap().name,
+            "REVERSAL FIELD 6"
+        );
+
+        // Type and validators of the new spec apply too
+        msg.set_mti(MTI(*b"0220"));
+        assert_eq!(
+            msg.reinterpret(&registry),
+            Err(RS8583Error::parse_error(
+                "field 2: invalid character 0x41 for type N"
+            ))
+        );
+        msg.set_mti(MTI(*b"0320"));
+        assert_eq!(
+            msg.reinterpret(&registry),
+            Err(RS8583Error::parse_error("field 4: XY not accepted"))
+        );
+        assert_eq!(
+            msg.spec().fields[6].as_ref().unwrap().name,
+            "REVERSAL FIELD 6"
+        );
+
+        msg.set_mti(MTI(*b"0800"));
+        assert_eq!(
+            msg.reinterpret(&registry),
+            Err(RS8583Error::ParseError {
+                error: String::from("No spec registered for MTI 0800"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_serialize_chunks() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0120\x56\x00\x00\x00\x00\x00\x00\x00111122223333ABCDXY05LLVAR".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let chunks = msg
+            .serialize_chunks(&codec)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(chunks.len(), 6);
+        assert_eq!(chunks[0].as_ref(), b"0120");
+        assert_eq!(chunks[5].as_ref(), b"05LLVAR");
+        assert_eq!(chunks.concat(), msg.serialize(&codec)?.as_ref());
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_bcd_mti() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            mti_encoding: MTIEncoding::BCD,
+            ..Codec::default()
+        };
+        let spec = test_spec();
+        let raw = b"\x02\x00\x04\x00\x00\x00\x00\x00\x00\x00ABCD".to_vec();
+        let orig_raw = raw.clone();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let mti = msg.mti();
+        assert_eq!(mti.as_bytes(), b"0200");
+        assert!(mti.is_version_1987());
+        assert!(mti.is_financial());
+        assert!(mti.is_request());
+        assert!(mti.is_from_acquirer());
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"ABCD");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+        let raw = b"\x02\x0a\x04\x00\x00\x00\x00\x00\x00\x00ABCD".to_vec();
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw)).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Invalid BCD MTI byte: 0x0a"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_build() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let msg = Message::new(&spec);
+        assert_eq!(msg.mti().as_bytes(), b"0000");
+        assert!(msg.is_empty());
+
+        let mut msg = Message::with_mti(&spec, MTI(*b"0200"));
+        msg.set_field(1, "111122223333")?;
+        msg.set_field(2, "ABCD")?;
+        msg.set_field(6, "LLVAR")?;
+        assert_eq!(
+            msg.serialize(&codec)?.as_ref(),
+            &b"0200\x46\x00\x00\x00\x00\x00\x00\x00111122223333ABCD05LLVAR"[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_serialize_hook() -> Result<(), RS8583Error> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let stan = counter.clone();
+        spec.add_serialize_hook(move |msg| {
+            let next = stan.fetch_add(1, Ordering::SeqCst) + 1;
+            msg.set_field(4, format!("{:02}", next))
+        });
+
+        let mut msg = Message::with_mti(&spec, MTI(*b"0200"));
+        msg.set_field(1, "111122223333")?;
+        assert_eq!(
+            msg.serialize(&codec)?.as_ref(),
+            &b"0200\x12\x00\x00\x00\x00\x00\x00\x0011112222333301"[..]
+        );
+        assert_eq!(&msg.serialize(&codec)?[24..], b"02");
+        assert!(msg.field(4).is_none());
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        msg.apply_serialize_hooks()?;
+        assert_eq!(msg.field(4).unwrap().as_slice(), b"03");
+
+        Ok(())
+    }
+
+    #[test]
+    fn mti_to_response() -> Result<(), RS8583Error> {
+        let response = |mti: &str| -> Result<String, RS8583Error> {
+            let mti = mti.parse::<MTI>()?.to_response()?;
+            Ok(String::from_utf8_lossy(mti.as_bytes()).into_owned())
+        };
+        assert_eq!(response("0200")?, "0210");
+        assert_eq!(response("0100")?, "0110");
+        assert_eq!(response("0420")?, "0430");
+        assert_eq!(response("0644")?, "0654");
+        // The origin digit is kept, repeats and issuer-originated included
+        assert_eq!(response("0201")?, "0211");
+        assert_eq!(response("0422")?, "0432");
+        assert_eq!(
+            response("0210").err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("MTI 0210 has no response"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn mti_constructors() -> Result<(), RS8583Error> {
+        let mti = MTI::new(b'0', b'2', b'0', b'0')?;
+        assert_eq!(mti.as_bytes(), b"0200");
+        assert!(mti.is_financial() && mti.is_request());
+        assert_eq!("0210".parse::<MTI>()?.as_bytes(), b"0210");
+
+        assert_eq!(
+            MTI::new(b'0', b'2', b'x', b'0').err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("MTI contains non-digit 0x78"),
+            })
+        );
+        assert_eq!(
+            "200".parse::<MTI>().err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("MTI must be 4 digits, got \"200\""),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_canonical_bytes() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            framing: Framing::MHeader,
+            ..Codec::default()
+        };
+        let mut spec = test_spec();
+        spec.fields.resize_with(64, || None);
+        spec.fields[63] = Some(FieldSpec {
+            name: String::from("MAC"),
+            field_type: FieldType::B,
+            length: 8,
+            ..FieldSpec::default()
+        });
+        let mut msg = Message::with_mti(&spec, MTI(*b"0200"));
+        msg.set_field(2, "ABCD")?;
+        msg.set_field(63, &b"\x11\x22\x33\x44\x55\x66\x77\x88"[..])?;
+
+        let canonical = msg.canonical_bytes(&codec, &[63])?;
+        assert_eq!(
+            canonical.as_ref(),
+            &b"0200\x04\x00\x00\x00\x00\x00\x00\x80ABCD"[..]
+        );
+        assert_eq!(&msg.serialize(&codec)?[2..18], canonical.as_ref());
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_clear_field_compacts() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = test_spec();
+        spec.fields.resize_with(192, || None);
+        spec.fields[130] = Some(FieldSpec {
+            name: String::from("TERTIARY FIELD"),
+            length: 2,
+            ..FieldSpec::default()
+        });
+        let mut msg = Message::with_mti(&spec, MTI(*b"0200"));
+        msg.set_field(2, "ABCD")?;
+        msg.set_field(130, "ZZ")?;
+        assert_eq!(msg.serialize(&codec)?.len(), 4 + 24 + 4 + 2);
+
+        msg.clear_field(130);
+        assert_eq!(
+            msg.serialize(&codec)?.as_ref(),
+            &b"0200\x04\x00\x00\x00\x00\x00\x00\x00ABCD"[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_from_template() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0200\x14\x00\x00\x00\x00\x00\x00\x00ABCDXY".to_vec();
+        let template = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+        let mut msg = template.from_template();
+        msg.set_field(1, "111122223333")?;
+        msg.set_field(4, "ZZ")?;
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"ABCD");
+        assert_eq!(msg.field(4).unwrap().as_slice(), b"ZZ");
+        assert_eq!(
+            msg.serialize(&codec)?.as_ref(),
+            &b"0200\x16\x00\x00\x00\x00\x00\x00\x00111122223333ABCDZZ"[..]
+        );
+
+        assert!(template.field(1).is_none());
+        assert_eq!(template.field(4).unwrap().as_slice(), b"XY");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_spec_validators() -> Result<(), RS8583Error> {
+        let mut spec = test_spec();
+        spec.fields.resize_with(128, || None);
+        spec.fields[24] = Some(FieldSpec {
+            name: String::from("POS CONDITION CODE"),
+            field_type: FieldType::N,
+            length: 2,
+            ..FieldSpec::default()
+        });
+        spec.add_validator(24, |field| match field.as_slice() {
+            b"00" | b"01" | b"02" | b"08" => Ok(()),
+            _ => Err(String::from("unknown POS condition code")),
+        });
+        let raw = b"0200\x00\x00\x00\x01\x00\x00\x00\x0077".to_vec();
+
+        let msg = Message::from_bytes(&spec, &Codec::default(), Bytes::from(raw.clone()))?;
+        assert_eq!(
+            msg.validate(),
+            Err(vec![RS8583Error::ParseError {
+                error: String::from("field 24: unknown POS condition code"),
+            }])
+        );
+
+        let strict = Codec {
+            strict_field_validation: true,
+            ..Codec::default()
+        };
+        assert_eq!(
+            Message::from_bytes(&spec, &strict, Bytes::from(raw)).err(),
+            Some(RS8583Error::field_parse_error(
+                24,
+                "POS CONDITION CODE",
+                RS8583Error::parse_error("unknown POS condition code"),
+            ))
+        );
+
+        let raw = b"0200\x00\x00\x00\x01\x00\x00\x00\x0002".to_vec();
+        let msg = Message::from_bytes(&spec, &strict, Bytes::from(raw))?;
+        assert_eq!(msg.validate(), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_masked_by_policy() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let mut spec = MessageSpec {
+            fields: vec![
+                None,
+                Some(FieldSpec {
+                    name: String::from("PAN"),
+                    field_type: FieldType::N,
+                    length_type: LengthType::LLVar,
+                    length: 19,
+                    ..FieldSpec::default()
+                }),
+                Some(FieldSpec {
+                    name: String::from("PROCESSING CODE"),
+                    field_type: FieldType::N,
+                    length: 6,
+                    ..FieldSpec::default()
+                }),
+            ],
+            ..MessageSpec::default()
+        };
+        let raw = b"0200\x06\x00\x00\x00\x00\x00\x00\x00164111111111111111000000".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw.clone()))?;
+        assert_eq!(msg.masked_field(1).unwrap().as_ref(), b"411111******1111");
+        assert_eq!(msg.masked_field(2).unwrap().as_ref(), b"000000");
+
+        assert_eq!(
+            format!("{:?}", msg.masked(1).unwrap()),
+            "\"411111******1111\""
+        );
+        assert_eq!(
+            msg.masked_display(),
+            "MTI 0200\nDE2 PAN: 411111******1111\nDE3 PROCESSING CODE: 000000"
+        );
+
+        spec.fields[1].as_mut().unwrap().sensitivity = SensitivityType::Normal;
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw.clone()))?;
+        assert_eq!(msg.masked_field(1).unwrap().as_ref(), b"4111111111111111");
+
+        spec.fields[1].as_mut().unwrap().sensitivity = SensitivityType::Policy;
+        spec.sensitivity_policy = SensitivityPolicy::none();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        assert_eq!(msg.masked_field(1).unwrap().as_ref(), b"4111111111111111");
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_serialize_field_error() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let spec = test_spec();
+        let raw = b"0120\x02\x00\x00\x00\x00\x00\x00\x00111122223333".to_vec();
+        let mut msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+        msg.set_field_raw(1, "123")?;
+
+        match msg.serialize(&codec) {
+            Err(RS8583Error::FieldParseError { field, source, .. }) => {
+                assert_eq!(field, 1);
+                assert_eq!(
+                    source.to_string(),
+                    "ISO8583 parse error: TEST FIELD 2: length 3 below fixed length 12"
+                );
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_mheader_framing() -> Result<(), RS8583Error> {
+        let codec = Codec {
+            framing: Framing::MHeader,
+            ..Codec::default()
+        };
+        let spec = test_spec();
+        let raw = b"\x00\x1c0120\x06\x00\x00\x00\x00\x00\x00\x001111222233331234".to_vec();
+        let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw.clone()))?;
+        assert_eq!(msg.field(2).unwrap().as_slice(), b"1234");
+        assert_eq!(msg.serialize(&codec)?.as_ref(), &raw[..]);
+        assert_eq!(msg.field_layout(&codec)?[0], (1, 14..26));
+
+        let mut long = raw.clone();
+        long.push(b'X');
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(long)).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Frame length 28 disagrees with message length 29"),
+            })
+        );
+        assert_eq!(
+            Message::from_bytes(&spec, &codec, Bytes::from(raw[..29].to_vec())).err(),
+            Some(RS8583Error::ParseError {
+                error: String::from("Truncated frame (28 bytes declared, 27 available)"),
+            })
+        );
+
+        Ok(())
+    }
 }
@@ -1,32 +1,60 @@
 use crate::error::RS8583Error;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
 use std::cmp::min;
+#[cfg(feature = "serde")]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
 
+use crate::bitmap::BitMap;
 use crate::codec::Codec;
 use crate::field::Field;
+#[cfg(feature = "serde")]
+use crate::msg::MAX_FIELD_INDEX;
+use crate::msg::{Message, MTI};
+use crate::subfield::SubfieldSpec;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum FieldType {
     A,
     N,
     S,
     NS,
     AN,
+    #[default]
     ANS,
+    /// Opaque binary data, stored and re-emitted byte for byte. Also suits
+    /// national and private use fields that have no detailed spec.
     B,
 }
 
-impl Default for FieldType {
-    fn default() -> Self {
-        FieldType::ANS
-    }
-}
-
+/// With the `serde` feature, length types are named in upper case, e.g.
+/// `"LLVAR"`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "UPPERCASE")
+)]
 pub enum LengthType {
+    #[default]
     Fixed,
     LVar,
     LLVar,
     LLLVar,
     LLLLVar,
+    /// Variable length with a binary length prefix of 1 or 2 bytes, preceded
+    /// by a marker byte. The high nibble of the marker is the number of
+    /// length bytes that follow, the low nibble is zero, and the length bytes
+    /// are an unsigned big-endian count: `10 05 "HELLO"`, `20 01 2C <300
+    /// bytes>`. Serialize uses the 1-byte form whenever the length fits.
+    /// Independent of the codec length settings.
+    MarkedVar,
     BitMap,
 }
 
@@ -42,31 +70,155 @@ impl LengthType {
     }
 }
 
-impl Default for LengthType {
-    fn default() -> Self {
-        LengthType::Fixed
-    }
-}
-
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SensitivityType {
+    /// Defer to the [`SensitivityPolicy`] of the message spec.
+    #[default]
+    Policy,
     Normal,
     MaskPAN,
     MaskAll,
 }
 
-impl Default for SensitivityType {
+impl SensitivityType {
+    /// Mask `data` for display. `MaskPAN` keeps the first 6 and last 4
+    /// characters of values long enough to hide anything in between.
+    pub fn mask<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            Self::Policy | Self::Normal => Cow::Borrowed(data),
+            Self::MaskPAN if data.len() > 10 => {
+                let mut masked = data.to_vec();
+                for b in &mut masked[6..data.len() - 4] {
+                    *b = b'*';
+                }
+                Cow::Owned(masked)
+            }
+            Self::MaskPAN | Self::MaskAll => Cow::Owned(vec![b'*'; data.len()]),
+        }
+    }
+}
+
+/// Sensitivity applied to fields whose spec leaves it at
+/// `SensitivityType::Policy`. Field rules take precedence over type rules.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SensitivityPolicy {
+    pub fields: Vec<(usize, SensitivityType)>,
+    pub field_types: Vec<(FieldType, SensitivityType)>,
+}
+
+impl Default for SensitivityPolicy {
+    /// Masks the PAN (DE2), track 2 and track 1 data (DE35, DE45), PIN data
+    /// (DE52) and all binary fields.
     fn default() -> Self {
-        SensitivityType::Normal
+        SensitivityPolicy {
+            fields: vec![
+                (1, SensitivityType::MaskPAN),
+                (34, SensitivityType::MaskAll),
+                (44, SensitivityType::MaskAll),
+                (51, SensitivityType::MaskAll),
+            ],
+            field_types: vec![(FieldType::B, SensitivityType::MaskAll)],
+        }
     }
 }
 
-#[derive(Default)]
+impl SensitivityPolicy {
+    /// Policy that leaves every field unmasked.
+    pub fn none() -> Self {
+        SensitivityPolicy {
+            fields: Vec::new(),
+            field_types: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&self, idx: usize, field_type: FieldType) -> SensitivityType {
+        let by_field = self.fields.iter().find(|(i, _)| *i == idx);
+        let by_type = || self.field_types.iter().find(|(t, _)| *t == field_type);
+        by_field
+            .map(|(_, sensitivity)| *sensitivity)
+            .or_else(|| by_type().map(|(_, sensitivity)| *sensitivity))
+            .unwrap_or(SensitivityType::Normal)
+    }
+}
+
+/// Pad character and side used by values of a field.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Padding {
+    #[default]
+    None,
+    /// Value is right-justified, padded with leading characters.
+    Left(u8),
+    /// Value is left-justified, padded with trailing characters.
+    Right(u8),
+}
+
+impl Padding {
+    pub fn trim<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        match *self {
+            Self::None => data,
+            Self::Left(pad) => {
+                let start = data.iter().position(|b| *b != pad).unwrap_or(data.len());
+                &data[start..]
+            }
+            Self::Right(pad) => {
+                let end = data.iter().rposition(|b| *b != pad).map_or(0, |i| i + 1);
+                &data[..end]
+            }
+        }
+    }
+}
+
+/// With the `serde` feature, field specs serialize as in spec files (see
+/// `MessageSpec::from_yaml_str`), where only `name` and `length` are
+/// mandatory.
+#[derive(Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(deny_unknown_fields)
+)]
 pub struct FieldSpec {
     pub name: String,
+    #[cfg_attr(feature = "serde", serde(rename = "type", default))]
     pub field_type: FieldType,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub length_type: LengthType,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub sensitivity: SensitivityType,
     pub length: usize,
+    /// Minimum data length for variable length fields. Zero means no minimum
+    /// is enforced.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub min_length: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub padding: Padding,
+    /// Strip `padding` from variable length values before computing the
+    /// length prefix on serialize.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trim_padding: bool,
+    /// Number of filler bytes following the field data on the wire. Skipped on
+    /// parse and written as zero bytes on serialize.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub filler: usize,
+    /// Data is packed as BCD, two digits per byte. `length`, `min_length` and
+    /// length prefixes then count digits rather than bytes, so a fixed field
+    /// of length 8 occupies 4 bytes. Without it all lengths are byte counts,
+    /// including for `FieldType::B`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bcd: bool,
+    /// The length prefix counts its own bytes as well as the data, as in
+    /// some legacy protocols: an LLVAR prefix "07" announces 5 data bytes.
+    /// Applies to the `LVar` to `LLLLVar` length types.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub length_includes_prefix: bool,
+    /// Layout of the subfields packed into the value, for private use fields
+    /// such as DE48. Values are still parsed as a whole; see
+    /// [`Field::subfields`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub subfields: Option<SubfieldSpec>,
 }
 
 impl FieldSpec {
@@ -74,22 +226,103 @@ impl FieldSpec {
         // TODO: support codecs for LL
         match self.length_type {
             LengthType::Fixed => self.length,
-            LengthType::LVar => 1,
-            LengthType::LLVar => 1,
-            LengthType::LLLVar => 1,
-            LengthType::LLLLVar => 1,
+            LengthType::LVar => self.min_length.max(1),
+            LengthType::LLVar => self.min_length.max(1),
+            LengthType::LLLVar => self.min_length.max(1),
+            LengthType::LLLLVar => self.min_length.max(1),
+            LengthType::MarkedVar => self.min_length.max(1),
             _ => 0,
         }
     }
 
+    /// Check data length of field `idx` against the (optional) minimum length.
+    pub fn check_min_length(&self, idx: usize, len: usize) -> Result<(), RS8583Error> {
+        self.check_min(len).map_err(|e| e.in_field(idx))
+    }
+
+    fn check_min(&self, len: usize) -> Result<(), RS8583Error> {
+        match self.length_type {
+            LengthType::Fixed | LengthType::BitMap => Ok(()),
+            _ if len < self.min_length => Err(RS8583Error::parse_error(format!(
+                "length {} below min {}",
+                len, self.min_length
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check data length of field `idx` against all length constraints.
+    pub fn check_length(&self, idx: usize, len: usize) -> Result<(), RS8583Error> {
+        self.check_len(len).map_err(|e| e.in_field(idx))
+    }
+
+    fn check_len(&self, len: usize) -> Result<(), RS8583Error> {
+        match self.length_type {
+            LengthType::BitMap => Ok(()),
+            LengthType::Fixed if len != self.length => Err(RS8583Error::parse_error(format!(
+                "length {} differs from fixed length {}",
+                len, self.length
+            ))),
+            LengthType::Fixed => Ok(()),
+            _ if len > self.max_value_size() => Err(RS8583Error::parse_error(format!(
+                "length {} above max {}",
+                len,
+                self.max_value_size()
+            ))),
+            _ => self.check_min(len),
+        }
+    }
+
+    /// Check value `data` of field `idx` against the length constraints and
+    /// the character set of the field type. Padding characters are always
+    /// accepted.
+    pub fn validate_value(&self, idx: usize, data: &[u8]) -> Result<(), RS8583Error> {
+        self.check_value(data).map_err(|e| e.in_field(idx))
+    }
+
+    /// [`FieldSpec::validate_value`], with errors left for the caller to
+    /// attribute to a field.
+    pub(crate) fn check_value(&self, data: &[u8]) -> Result<(), RS8583Error> {
+        self.check_len(data.len())?;
+        let special = |b: u8| (b.is_ascii_graphic() && !b.is_ascii_alphanumeric()) || b == b' ';
+        let valid = |b: u8| match self.field_type {
+            FieldType::A => b.is_ascii_alphabetic() || b == b' ',
+            FieldType::N => b.is_ascii_digit(),
+            FieldType::S => special(b),
+            FieldType::NS => b.is_ascii_digit() || special(b),
+            FieldType::AN => b.is_ascii_alphanumeric() || b == b' ',
+            FieldType::ANS => b.is_ascii_graphic() || b == b' ',
+            FieldType::B => true,
+        };
+        let pad = match self.padding {
+            Padding::None => None,
+            Padding::Left(pad) | Padding::Right(pad) => Some(pad),
+        };
+        match data.iter().find(|b| !valid(**b) && Some(**b) != pad) {
+            Some(b) => Err(RS8583Error::parse_error(format!(
+                "invalid character 0x{:02x} for type {:?}",
+                b, self.field_type
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// With `length_includes_prefix`, the prefix takes its share of the
+    /// largest value the prefix can express, counted as one byte per digit.
     pub fn max_value_size(&self) -> usize {
         // TODO: support codecs for LL
+        let prefix = if self.length_includes_prefix {
+            self.length_type.length_size()
+        } else {
+            0
+        };
         match self.length_type {
             LengthType::Fixed => self.length,
-            LengthType::LVar => min(self.length, 9),
-            LengthType::LLVar => min(self.length, 99),
-            LengthType::LLLVar => min(self.length, 999),
-            LengthType::LLLLVar => min(self.length, 9999),
+            LengthType::LVar => min(self.length, 9 - prefix),
+            LengthType::LLVar => min(self.length, 99 - prefix),
+            LengthType::LLLVar => min(self.length, 999 - prefix),
+            LengthType::LLLLVar => min(self.length, 9999 - prefix),
+            LengthType::MarkedVar => min(self.length, 0xffff),
             _ => 0,
         }
     }
@@ -110,12 +343,28 @@ impl FieldSpec {
                 cursor.remaining()
             )));
         }
+        let prefix_size = len;
+        let pad = codec.length_pad_byte();
+        let mut leading = true;
         let mut sz: usize = 0;
         while len > 0 {
             let len_byte = cursor.get_u8();
-            sz += codec.byte_to_length(len_byte)? * 10usize.pow(len as u32 - 1);
+            // Only the high digits may be padding, never the last one
+            leading &= len > 1 && Some(len_byte) == pad;
+            sz *= codec.length_byte_radix();
+            if !leading {
+                sz += codec.byte_to_length(len_byte)?;
+            }
             len -= 1;
         }
+        if self.length_includes_prefix {
+            sz = sz.checked_sub(prefix_size).ok_or_else(|| {
+                RS8583Error::parse_error(format!(
+                    "Length prefix {} shorter than the prefix itself",
+                    sz
+                ))
+            })?;
+        }
         if sz > self.length {
             return Err(RS8583Error::parse_error(format!(
                 "Variable length field over max length ({} > {})",
@@ -125,48 +374,697 @@ impl FieldSpec {
         Ok(sz)
     }
 
-    pub fn to_read(&self, codec: &Codec, cursor: &mut Bytes) -> Result<usize, RS8583Error> {
+    fn parse_marked_length(&self, cursor: &mut Bytes) -> Result<usize, RS8583Error> {
+        if !cursor.has_remaining() {
+            return Err(RS8583Error::parse_error("Unable to read length marker"));
+        }
+        let marker = cursor.get_u8();
+        let len = match marker {
+            0x10 => 1,
+            0x20 => 2,
+            _ => {
+                return Err(RS8583Error::parse_error(format!(
+                    "Invalid length marker: 0x{:02x}",
+                    marker
+                )))
+            }
+        };
+        if cursor.remaining() < len {
+            return Err(RS8583Error::parse_error(format!(
+                "Unable to read length prefix ({} chars needed, {} available)",
+                len,
+                cursor.remaining()
+            )));
+        }
+        let sz = if len == 1 {
+            cursor.get_u8() as usize
+        } else {
+            cursor.get_u16() as usize
+        };
+        if sz > self.length {
+            return Err(RS8583Error::parse_error(format!(
+                "Variable length field over max length ({} > {})",
+                sz, self.length
+            )));
+        }
+        Ok(sz)
+    }
+
+    /// Data length of the next value, in the unit of `length` (digits for BCD
+    /// fields, bytes otherwise). Consumes the length prefix, if any.
+    pub fn data_length(&self, codec: &Codec, cursor: &mut Bytes) -> Result<usize, RS8583Error> {
         match &self.length_type {
             LengthType::BitMap => Ok(0),
             LengthType::Fixed => Ok(self.length),
+            LengthType::MarkedVar => self.parse_marked_length(cursor),
             n => self.parse_length_prefix(codec, cursor, codec.length_size_bytes(n.length_size())),
         }
     }
 
+    /// Number of bytes occupied on the wire by data of length `len`.
+    pub fn wire_size(&self, len: usize) -> usize {
+        if self.bcd {
+            len.div_ceil(2)
+        } else {
+            len
+        }
+    }
+
+    /// Parse the next value from `cursor`: length prefix, data (unpacked or
+    /// decoded per the codec) and filler. With a faithful codec the field
+    /// keeps its wire bytes.
+    pub fn parse_value(&self, codec: &Codec, cursor: &mut Bytes) -> Result<Field, RS8583Error> {
+        let start = cursor.clone();
+        let len = self.data_length(codec, cursor)?;
+        self.check_min(len)?;
+        let to_read = self.wire_size(len);
+        if cursor.remaining() < to_read {
+            return Err(RS8583Error::parse_error("Truncated field"));
+        }
+        let data = if self.bcd {
+            Bytes::from(codec.unpack_bcd(&cursor[..to_read], len)?)
+        } else {
+            codec.decode_data(self.field_type, cursor.slice(..to_read))
+        };
+        cursor.advance(to_read);
+        if cursor.remaining() < self.filler {
+            return Err(RS8583Error::parse_error("Truncated filler"));
+        }
+        cursor.advance(self.filler);
+        Ok(if codec.faithful {
+            Field::with_raw(data, start.slice(..start.len() - cursor.len()))
+        } else {
+            Field::from_bytes(data)
+        })
+    }
+
+    /// Number of data bytes to read for the next value. Consumes the length
+    /// prefix, if any.
+    pub fn to_read(&self, codec: &Codec, cursor: &mut Bytes) -> Result<usize, RS8583Error> {
+        Ok(self.wire_size(self.data_length(codec, cursor)?))
+    }
+
+    fn write_data(
+        &self,
+        codec: &Codec,
+        buf: &mut BytesMut,
+        data: &[u8],
+    ) -> Result<(), RS8583Error> {
+        if self.bcd {
+            buf.extend_from_slice(&codec.pack_bcd(data)?);
+        } else {
+            buf.extend_from_slice(&codec.encode_data(self.field_type, data));
+        }
+        Ok(())
+    }
+
+    /// Number of bytes [`FieldSpec::serialize_field`] writes for `field`,
+    /// length prefix and filler included.
+    pub fn serialized_len(&self, codec: &Codec, field: &Field) -> usize {
+        if codec.faithful {
+            if let Some(raw) = field.raw() {
+                return raw.len();
+            }
+        }
+        let data_len = match self.length_type {
+            LengthType::BitMap => return self.filler,
+            LengthType::Fixed => field.len(),
+            _ if self.trim_padding => self.padding.trim(field.as_slice()).len(),
+            _ => field.len(),
+        };
+        let prefix_len = match self.length_type {
+            LengthType::BitMap | LengthType::Fixed => 0,
+            LengthType::MarkedVar if data_len <= 0xff => 2,
+            LengthType::MarkedVar => 3,
+            n => codec.length_size_bytes(n.length_size()),
+        };
+        prefix_len + self.wire_size(data_len) + self.filler
+    }
+
     pub fn serialize_field(
         &self,
         codec: &Codec,
         buf: &mut BytesMut,
         field: &Field,
     ) -> Result<(), RS8583Error> {
+        if codec.faithful {
+            if let Some(raw) = field.raw() {
+                buf.extend_from_slice(raw);
+                return Ok(());
+            }
+        }
         match &self.length_type {
             LengthType::BitMap => Ok(()),
             LengthType::Fixed => {
-                if self.length == field.len() {
-                    buf.extend_from_slice(field.as_slice());
-                    Ok(())
+                if field.len() < self.min_value_size() {
+                    Err(RS8583Error::parse_error(format!(
+                        "{}: length {} below fixed length {}",
+                        self.name,
+                        field.len(),
+                        self.length
+                    )))
+                } else if self.length == field.len() {
+                    self.write_data(codec, buf, field.as_slice())
                 } else {
-                    Err(RS8583Error::parse_error("Invalid field length"))
+                    Err(RS8583Error::parse_error(format!(
+                        "{}: length {} above fixed length {}",
+                        self.name,
+                        field.len(),
+                        self.length
+                    )))
                 }
             }
             n => {
-                // TODO: check max data_len
-                codec.serialize_prefix(buf, n.length_size(), field.len())?;
-                buf.extend_from_slice(field.as_slice());
-                Ok(())
+                let data = if self.trim_padding {
+                    self.padding.trim(field.as_slice())
+                } else {
+                    field.as_slice()
+                };
+                if data.len() > self.max_value_size() {
+                    return Err(RS8583Error::parse_error(format!(
+                        "{}: length {} above max {}",
+                        self.name,
+                        data.len(),
+                        self.max_value_size()
+                    )));
+                }
+                match n {
+                    LengthType::MarkedVar if data.len() <= 0xff => {
+                        buf.put_u8(0x10);
+                        buf.put_u8(data.len() as u8);
+                    }
+                    LengthType::MarkedVar => {
+                        buf.put_u8(0x20);
+                        buf.put_u16(data.len() as u16);
+                    }
+                    _ if self.length_includes_prefix => {
+                        let prefix_size = codec.length_size_bytes(n.length_size());
+                        codec.serialize_prefix(buf, n.length_size(), data.len() + prefix_size)?
+                    }
+                    _ => codec.serialize_prefix(buf, n.length_size(), data.len())?,
+                }
+                self.write_data(codec, buf, data)
+            }
+        }?;
+        buf.resize(buf.len() + self.filler, 0);
+        Ok(())
+    }
+}
+
+/// Scheme-specific check of a field value, returning a description of the
+/// problem on failure.
+pub type FieldValidator = Box<dyn Fn(&Field) -> Result<(), String> + Send + Sync>;
+
+/// Adjustment applied to every message before it is serialized, such as
+/// stamping the transmission time.
+pub type SerializeHook = Box<dyn Fn(&mut Message) -> Result<(), RS8583Error> + Send + Sync>;
+
+/// Maps keyed by MTI, serialized with the MTI as a string key.
+#[cfg(feature = "serde")]
+mod mti_keys {
+    use super::*;
+
+    pub fn serialize<S, V>(map: &HashMap<[u8; 4], V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        V: Serialize,
+    {
+        let sorted: BTreeMap<String, &V> = map
+            .iter()
+            .map(|(mti, v)| (String::from_utf8_lossy(mti).into_owned(), v))
+            .collect();
+        sorted.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, V>(deserializer: D) -> Result<HashMap<[u8; 4], V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        let map = BTreeMap::<String, V>::deserialize(deserializer)?;
+        map.into_iter()
+            .map(|(mti, v)| match *mti.as_bytes() {
+                [a, b, c, d] => Ok(([a, b, c, d], v)),
+                _ => Err(serde::de::Error::custom(format!(
+                    "Invalid MTI key \"{}\"",
+                    mti
+                ))),
+            })
+            .collect()
+    }
+}
+
+/// Data element numbers as map keys. Flattened maps hand their keys over as
+/// strings, even those written as numbers.
+#[cfg(feature = "serde")]
+mod de_keys {
+    use super::*;
+
+    pub fn serialize<S, V>(map: &BTreeMap<usize, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        V: Serialize,
+    {
+        map.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, V>(deserializer: D) -> Result<BTreeMap<usize, V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        V: Deserialize<'de>,
+    {
+        let map = BTreeMap::<String, V>::deserialize(deserializer)?;
+        map.into_iter()
+            .map(|(de, v)| match de.parse() {
+                Ok(de) => Ok((de, v)),
+                Err(_) => Err(serde::de::Error::custom(format!(
+                    "Invalid data element number \"{}\"",
+                    de
+                ))),
+            })
+            .collect()
+    }
+}
+
+/// Spec file contents, the serialized form of a `MessageSpec` in all
+/// formats: field specs keyed by data element number, next to the optional
+/// `dependencies`, `sensitivity_policy` and `presence_policies` keys, which
+/// name fields by data element number too.
+/// Numbers of bitmap continuation bits (DE1, DE65, DE129) are rejected, while
+/// numbers beyond the last field a message can hold (`MAX_FIELD_INDEX + 1`)
+/// are tolerated and skipped. The field list is sized to whole bitmap chunks
+/// covering the highest number kept, with `None` in the gaps.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SpecFile {
+    #[serde(default)]
+    dependencies: Vec<(usize, usize)>,
+    #[serde(default)]
+    sensitivity_policy: SensitivityPolicy,
+    #[serde(default, with = "mti_keys")]
+    presence_policies: HashMap<[u8; 4], PresencePolicy>,
+    #[serde(flatten, with = "de_keys")]
+    fields: BTreeMap<usize, FieldSpec>,
+}
+
+#[cfg(feature = "serde")]
+impl From<&MessageSpec> for SpecFile {
+    /// Entries at continuation bits are left out, as they only document the
+    /// bitmap.
+    fn from(spec: &MessageSpec) -> Self {
+        let des = |list: &[usize]| list.iter().map(|idx| idx + 1).collect();
+        SpecFile {
+            dependencies: spec
+                .dependencies
+                .iter()
+                .map(|(idx, requires)| (idx + 1, requires + 1))
+                .collect(),
+            sensitivity_policy: SensitivityPolicy {
+                fields: spec
+                    .sensitivity_policy
+                    .fields
+                    .iter()
+                    .map(|(idx, sensitivity)| (idx + 1, *sensitivity))
+                    .collect(),
+                field_types: spec.sensitivity_policy.field_types.clone(),
+            },
+            presence_policies: spec
+                .presence_policies
+                .iter()
+                .map(|(mti, policy)| {
+                    let policy = PresencePolicy {
+                        mandatory: des(&policy.mandatory),
+                        optional: des(&policy.optional),
+                        conditional: des(&policy.conditional),
+                    };
+                    (*mti, policy)
+                })
+                .collect(),
+            fields: spec
+                .fields
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| idx % 64 != 0)
+                .filter_map(|(idx, fs)| Some((idx + 1, fs.clone()?)))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SpecFile> for MessageSpec {
+    type Error = RS8583Error;
+
+    fn try_from(file: SpecFile) -> Result<Self, Self::Error> {
+        let idx = |de: usize| {
+            de.checked_sub(1)
+                .ok_or_else(|| RS8583Error::config_error("Data element numbers start at 1"))
+        };
+        let indices = |list: Vec<usize>| list.into_iter().map(idx).collect::<Result<Vec<_>, _>>();
+        if file.fields.contains_key(&0) {
+            return Err(RS8583Error::config_error("Data element numbers start at 1"));
+        }
+        let dependencies = file
+            .dependencies
+            .into_iter()
+            .map(|(de, requires)| Ok((idx(de)?, idx(requires)?)))
+            .collect::<Result<_, RS8583Error>>()?;
+        let sensitivity_policy = SensitivityPolicy {
+            fields: file
+                .sensitivity_policy
+                .fields
+                .into_iter()
+                .map(|(de, sensitivity)| Ok((idx(de)?, sensitivity)))
+                .collect::<Result<_, RS8583Error>>()?,
+            field_types: file.sensitivity_policy.field_types,
+        };
+        let presence_policies = file
+            .presence_policies
+            .into_iter()
+            .map(|(mti, policy)| {
+                let policy = PresencePolicy {
+                    mandatory: indices(policy.mandatory)?,
+                    optional: indices(policy.optional)?,
+                    conditional: indices(policy.conditional)?,
+                };
+                Ok((mti, policy))
+            })
+            .collect::<Result<_, RS8583Error>>()?;
+        if let Some(de) = file.fields.keys().find(|de| *de % 64 == 1) {
+            return Err(RS8583Error::config_error(format!(
+                "DE{} is a bitmap continuation bit",
+                de
+            )));
+        }
+        // No message can carry fields past the last bitmap chunk
+        let last = file
+            .fields
+            .range(..=MAX_FIELD_INDEX + 1)
+            .next_back()
+            .map_or(0, |(de, _)| de - 1);
+        let mut fields: Vec<Option<FieldSpec>> = Vec::new();
+        // Whole bitmap chunks, at least primary and secondary
+        fields.resize_with((last / 64 + 1).max(2) * 64, || None);
+        for (de, fs) in file.fields {
+            if de > MAX_FIELD_INDEX + 1 {
+                break;
             }
+            fields[de - 1] = Some(fs);
         }
+        Ok(MessageSpec {
+            fields,
+            dependencies,
+            sensitivity_policy,
+            presence_policies,
+            ..MessageSpec::default()
+        })
     }
 }
 
+/// Which fields a message of a given MTI may carry, by field index. Fields
+/// in none of the lists are forbidden. Conditional fields are allowed like
+/// optional ones; their conditions are expressed as spec dependencies.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PresencePolicy {
+    pub mandatory: Vec<usize>,
+    pub optional: Vec<usize>,
+    pub conditional: Vec<usize>,
+}
+
+impl PresencePolicy {
+    /// Check the fields set in `bitmap`, of a message with MTI `mti`.
+    pub fn check(&self, mti: &MTI, bitmap: &BitMap) -> Vec<RS8583Error> {
+        let mti = String::from_utf8_lossy(mti.as_bytes());
+        let missing = self
+            .mandatory
+            .iter()
+            .filter(|idx| !bitmap.test(**idx))
+            .map(|idx| {
+                RS8583Error::parse_error(format!(
+                    "DE{} mandatory for MTI {} but missing",
+                    idx + 1,
+                    mti
+                ))
+            });
+        let allowed = |idx: &usize| {
+            self.mandatory.contains(idx)
+                || self.optional.contains(idx)
+                || self.conditional.contains(idx)
+        };
+        let forbidden = bitmap.iter_set().filter(|idx| !allowed(idx)).map(|idx| {
+            RS8583Error::parse_error(format!("DE{} not allowed for MTI {}", idx + 1, mti))
+        });
+        missing.chain(forbidden).collect()
+    }
+}
+
+/// Field specs by index; index `n` is data element `n + 1`.
+///
+/// Index 0 (like every 64th index) is the continuation bit, and the bitmap
+/// chunk it announces is always read and written by the message bitmap. A
+/// spec entry there, whether `LengthType::BitMap` or a fixed 8-byte binary
+/// field as some specs model DE1, only documents it: it is never read or
+/// written as field data.
+///
+/// With the `serde` feature, specs serialize in the spec file layout read by
+/// `MessageSpec::from_yaml_str` and `MessageSpec::from_json_str`,
+/// without their validators and serialize hooks, which are code.
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize), serde(try_from = "SpecFile"))]
 pub struct MessageSpec {
     pub fields: Vec<Option<FieldSpec>>,
+    pub validators: Vec<(usize, FieldValidator)>,
+    pub serialize_hooks: Vec<SerializeHook>,
+    /// Pairs of field indices `(present, required)`: whenever the first
+    /// field is present, the second one must be too.
+    pub dependencies: Vec<(usize, usize)>,
+    pub sensitivity_policy: SensitivityPolicy,
+    /// Presence policies by MTI, checked by `Message::validate`.
+    pub presence_policies: HashMap<[u8; 4], PresencePolicy>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for MessageSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SpecFile::from(self).serialize(serializer)
+    }
+}
+
+/// One row of a [`MessageSpec::describe`] table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldSpecSummary {
+    pub index: usize,
+    pub name: String,
+    pub field_type: FieldType,
+    pub length_type: LengthType,
+    pub min: usize,
+    pub max: usize,
+    pub sensitivity: SensitivityType,
+}
+
+impl MessageSpec {
+    /// Load field specs from YAML: a mapping of data element numbers to
+    /// field specs with `name`, `type`, `length_type`, `length`,
+    /// `sensitivity` and the other `FieldSpec` keys, where only `name` and
+    /// `length` are mandatory.
+    ///
+    /// ```yaml
+    /// 2: { name: PAN, type: N, length_type: LLVAR, length: 19, sensitivity: MaskPAN }
+    /// 3: { name: PROCESSING CODE, type: N, length: 6 }
+    /// ```
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_str(yaml: &str) -> Result<MessageSpec, RS8583Error> {
+        let file: SpecFile = serde_yaml::from_str(yaml)
+            .map_err(|e| RS8583Error::config_error(format!("Invalid YAML spec: {}", e)))?;
+        MessageSpec::try_from(file)
+    }
+
+    /// Load field specs from JSON, in the same layout as
+    /// [`MessageSpec::from_yaml_str`]:
+    ///
+    /// ```json
+    /// { "2": { "name": "PAN", "type": "N", "length_type": "LLVAR", "length": 19 } }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_json_str(json: &str) -> Result<MessageSpec, RS8583Error> {
+        let file: SpecFile = serde_json::from_str(json)
+            .map_err(|e| RS8583Error::config_error(format!("Invalid JSON spec: {}", e)))?;
+        MessageSpec::try_from(file)
+    }
+
+    /// Summarize all defined fields, in index order.
+    pub fn describe(&self) -> Vec<FieldSpecSummary> {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter_map(|(index, fs)| {
+                fs.as_ref().map(|fs| FieldSpecSummary {
+                    index,
+                    name: fs.name.clone(),
+                    field_type: fs.field_type,
+                    length_type: fs.length_type,
+                    min: fs.min_value_size(),
+                    max: fs.max_value_size(),
+                    sensitivity: self.sensitivity(index).unwrap(),
+                })
+            })
+            .collect()
+    }
+
+    /// Effective sensitivity of field `idx`, if defined: its own setting, or
+    /// else whatever the sensitivity policy prescribes.
+    pub fn sensitivity(&self, idx: usize) -> Option<SensitivityType> {
+        let fs = self.fields.get(idx)?.as_ref()?;
+        Some(match fs.sensitivity {
+            SensitivityType::Policy => self.sensitivity_policy.resolve(idx, fs.field_type),
+            sensitivity => sensitivity,
+        })
+    }
+
+    /// Register a hook run, in registration order, before serializing any
+    /// message of this spec.
+    pub fn add_serialize_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Message) -> Result<(), RS8583Error> + Send + Sync + 'static,
+    {
+        self.serialize_hooks.push(Box::new(hook));
+    }
+
+    /// Register an extra validator for field `idx`.
+    pub fn add_validator<F>(&mut self, idx: usize, validator: F)
+    where
+        F: Fn(&Field) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validators.push((idx, Box::new(validator)));
+    }
+
+    /// Run all validators registered for field `idx`.
+    pub fn validate_field(&self, idx: usize, field: &Field) -> Result<(), RS8583Error> {
+        self.run_validators(idx, field).map_err(|e| e.in_field(idx))
+    }
+
+    /// [`MessageSpec::validate_field`], with errors left for the caller to
+    /// attribute to the field.
+    pub(crate) fn run_validators(&self, idx: usize, field: &Field) -> Result<(), RS8583Error> {
+        for (_, validator) in self.validators.iter().filter(|(i, _)| *i == idx) {
+            validator(field).map_err(RS8583Error::parse_error)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_presence_policy(&mut self, mti: &[u8; 4], policy: PresencePolicy) {
+        self.presence_policies.insert(*mti, policy);
+    }
+
+    pub fn presence_policy(&self, mti: &MTI) -> Option<&PresencePolicy> {
+        self.presence_policies.get(mti.as_bytes())
+    }
+
+    /// Require field `requires` whenever field `idx` is present.
+    pub fn add_dependency(&mut self, idx: usize, requires: usize) {
+        self.dependencies.push((idx, requires));
+    }
+
+    /// Require the fields in `group` to be either all present or all absent.
+    pub fn add_group(&mut self, group: &[usize]) {
+        for idx in group {
+            for requires in group.iter().filter(|r| *r != idx) {
+                self.add_dependency(*idx, *requires);
+            }
+        }
+    }
+
+    /// Check the field dependencies against the set of present fields.
+    pub fn check_dependencies<F>(&self, is_present: F) -> Vec<RS8583Error>
+    where
+        F: Fn(usize) -> bool,
+    {
+        self.dependencies
+            .iter()
+            .filter(|(idx, requires)| is_present(*idx) && !is_present(*requires))
+            .map(|(idx, requires)| {
+                RS8583Error::parse_error(format!(
+                    "DE{} present but DE{} missing",
+                    idx + 1,
+                    requires + 1
+                ))
+            })
+            .collect()
+    }
+
+    /// Check the spec for authoring mistakes, such as two fields sharing the
+    /// same non-empty name.
+    pub fn validate(&self) -> Result<(), RS8583Error> {
+        for (idx, field_spec) in self.fields.iter().enumerate() {
+            let name = match field_spec {
+                Some(fs) if !fs.name.is_empty() => &fs.name,
+                _ => continue,
+            };
+            let dup = self.fields[..idx]
+                .iter()
+                .position(|fs| matches!(fs, Some(fs) if &fs.name == name));
+            if let Some(first) = dup {
+                return Err(RS8583Error::config_error(format!(
+                    "Duplicate field name \"{}\" at indices {} and {}",
+                    name, first, idx
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Message specs keyed by MTI, with an optional fallback.
+#[derive(Default)]
+pub struct SpecRegistry {
+    specs: HashMap<[u8; 4], MessageSpec>,
+    fallback: Option<MessageSpec>,
+}
+
+impl SpecRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, mti: &[u8; 4], spec: MessageSpec) {
+        self.specs.insert(*mti, spec);
+    }
+
+    /// Spec used for MTIs without a registered spec of their own.
+    pub fn set_fallback(&mut self, spec: MessageSpec) {
+        self.fallback = Some(spec);
+    }
+
+    pub fn resolve(&self, mti: &MTI) -> Option<&MessageSpec> {
+        self.specs.get(mti.as_bytes()).or(self.fallback.as_ref())
+    }
+
+    /// Attach a presence policy for `mti` to the spec that `mti` resolves
+    /// to, which must already be registered (or be the fallback).
+    pub fn set_presence_policy(
+        &mut self,
+        mti: &[u8; 4],
+        policy: PresencePolicy,
+    ) -> Result<(), RS8583Error> {
+        let spec = match self.specs.get_mut(mti) {
+            Some(spec) => spec,
+            None => self.fallback.as_mut().ok_or_else(|| {
+                RS8583Error::config_error(format!(
+                    "No spec registered for MTI {}",
+                    String::from_utf8_lossy(mti)
+                ))
+            })?,
+        };
+        spec.set_presence_policy(mti, policy);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::codec::{Encoding, LengthPadding, VariableLengthFormat};
 
     #[test]
     fn fs_to_read_fixed() {
@@ -178,6 +1076,7 @@ mod tests {
             length_type: LengthType::Fixed,
             sensitivity: SensitivityType::Normal,
             length: 8,
+            ..FieldSpec::default()
         };
 
         let mut bytes = Bytes::from("TEST1234");
@@ -185,6 +1084,78 @@ mod tests {
         assert_eq!(fs.to_read(&codec, &mut bytes).unwrap(), 8);
     }
 
+    #[test]
+    fn fs_to_read_binary_vs_bcd() {
+        let codec = Codec::default();
+
+        let binary = FieldSpec {
+            field_type: FieldType::B,
+            length: 8,
+            ..FieldSpec::default()
+        };
+        let bcd = FieldSpec {
+            field_type: FieldType::N,
+            length: 8,
+            bcd: true,
+            ..FieldSpec::default()
+        };
+        let mut bytes = Bytes::from_static(b"\x12\x34\x56\x78\x9a\xbc\xde\xf0");
+        assert_eq!(binary.to_read(&codec, &mut bytes), Ok(8));
+        assert_eq!(bcd.to_read(&codec, &mut bytes), Ok(4));
+
+        let odd = FieldSpec {
+            field_type: FieldType::N,
+            length_type: LengthType::LLVar,
+            length: 19,
+            bcd: true,
+            ..FieldSpec::default()
+        };
+        let mut bytes = Bytes::from("19");
+        assert_eq!(odd.data_length(&codec, &mut bytes), Ok(19));
+        assert_eq!(odd.wire_size(19), 10);
+
+        let mut buf = BytesMut::new();
+        odd.serialize_field(&codec, &mut buf, &Field::from_bytes(Bytes::from("123")))
+            .unwrap();
+        assert_eq!(&buf[..], b"03\x01\x23");
+    }
+
+    #[test]
+    fn fs_to_read_space_padded() {
+        let fs = FieldSpec {
+            length_type: LengthType::LLLVar,
+            length: 999,
+            ..FieldSpec::default()
+        };
+
+        let mut codec = Codec {
+            length_encoding: Encoding::EBCDIC,
+            ..Codec::default()
+        };
+        let mut bytes = Bytes::from_static(b"\x40\x40\xf5");
+        assert_eq!(
+            fs.to_read(&codec, &mut bytes),
+            Err(RS8583Error::ParseError {
+                error: String::from("Length byte out of range: 0x40"),
+            })
+        );
+
+        codec.length_padding = LengthPadding::Space;
+        for prefix in &[&b"\x40\x40\xf5"[..], b"\x40\xf0\xf5", b"\xf0\xf0\xf5"] {
+            let mut bytes = Bytes::from_static(prefix);
+            assert_eq!(fs.to_read(&codec, &mut bytes), Ok(5));
+        }
+        for prefix in &[&b"\x40\x40\x40"[..], b"\xf1\x40\xf5"] {
+            let mut bytes = Bytes::from_static(prefix);
+            assert_eq!(
+                fs.to_read(&codec, &mut bytes),
+                Err(RS8583Error::ParseError {
+                    error: String::from("Length byte out of range: 0x40"),
+                })
+            );
+        }
+    }
+
     #[test]
     fn fs_to_read_lvar() {
         let codec = Codec::default();
@@ -195,6 +1166,7 @@ mod tests {
             length_type: LengthType::LVar,
             sensitivity: SensitivityType::Normal,
             length: 8,
+            ..FieldSpec::default()
         };
 
         let mut bytes = Bytes::from("3ABC");
@@ -246,6 +1218,7 @@ mod tests {
             length_type: LengthType::LLVar,
             sensitivity: SensitivityType::Normal,
             length: 12,
+            ..FieldSpec::default()
         };
 
         let mut bytes = Bytes::from("03ABC");
@@ -324,6 +1297,7 @@ mod tests {
             length_type: LengthType::LLLVar,
             sensitivity: SensitivityType::Normal,
             length: 110,
+            ..FieldSpec::default()
         };
 
         let mut bytes = Bytes::from("003ABC");
@@ -415,4 +1389,624 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn ms_check_dependencies() {
+        let mut spec = MessageSpec::default();
+        // PIN data (DE52) requires security control information (DE53)
+        spec.add_dependency(51, 52);
+        assert_eq!(
+            spec.check_dependencies(|idx| idx == 51),
+            vec![RS8583Error::parse_error("DE52 present but DE53 missing")]
+        );
+        assert!(spec
+            .check_dependencies(|idx| idx == 51 || idx == 52)
+            .is_empty());
+        assert!(spec.check_dependencies(|_| false).is_empty());
+    }
+
+    #[test]
+    fn ms_validate_duplicate_names() {
+        let reserved = || {
+            Some(FieldSpec {
+                name: String::from("Reserved"),
+                length: 3,
+                ..FieldSpec::default()
+            })
+        };
+        let mut spec = MessageSpec {
+            fields: vec![None, reserved(), None, None, reserved()],
+            ..MessageSpec::default()
+        };
+        assert_eq!(
+            spec.validate(),
+            Err(RS8583Error::ConfigError {
+                error: String::from("Duplicate field name \"Reserved\" at indices 1 and 4"),
+            })
+        );
+
+        spec.fields[4].as_mut().unwrap().name = String::new();
+        spec.fields[2] = Some(FieldSpec::default());
+        assert_eq!(spec.validate(), Ok(()));
+    }
+
+    #[test]
+    fn fs_check_min_length() {
+        let mut fs = FieldSpec {
+            length_type: LengthType::LLVar,
+            length: 12,
+            ..FieldSpec::default()
+        };
+        assert_eq!(fs.min_value_size(), 1);
+        assert_eq!(fs.check_min_length(6, 0), Ok(()));
+
+        fs.min_length = 2;
+        assert_eq!(fs.min_value_size(), 2);
+        assert_eq!(fs.check_min_length(6, 2), Ok(()));
+        assert_eq!(
+            fs.check_min_length(6, 1),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 6: length 1 below min 2"),
+            })
+        );
+    }
+
+    #[test]
+    fn ms_describe() {
+        let spec = MessageSpec {
+            fields: vec![
+                None,
+                Some(FieldSpec {
+                    name: String::from("PAN"),
+                    field_type: FieldType::N,
+                    length_type: LengthType::LLVar,
+                    sensitivity: SensitivityType::MaskPAN,
+                    length: 19,
+                    ..FieldSpec::default()
+                }),
+                None,
+                Some(FieldSpec {
+                    name: String::from("AMOUNT"),
+                    field_type: FieldType::N,
+                    length: 12,
+                    ..FieldSpec::default()
+                }),
+            ],
+            ..MessageSpec::default()
+        };
+        assert_eq!(
+            spec.describe(),
+            vec![
+                FieldSpecSummary {
+                    index: 1,
+                    name: String::from("PAN"),
+                    field_type: FieldType::N,
+                    length_type: LengthType::LLVar,
+                    min: 1,
+                    max: 19,
+                    sensitivity: SensitivityType::MaskPAN,
+                },
+                FieldSpecSummary {
+                    index: 3,
+                    name: String::from("AMOUNT"),
+                    field_type: FieldType::N,
+                    length_type: LengthType::Fixed,
+                    min: 12,
+                    max: 12,
+                    sensitivity: SensitivityType::Normal,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fs_serialize_prefix_overflow() {
+        let codec = Codec::default();
+
+        let fs = FieldSpec {
+            name: String::from("TEST"),
+            length_type: LengthType::LLLLVar,
+            length: 20000,
+            ..FieldSpec::default()
+        };
+
+        let mut buf = BytesMut::new();
+        let field = Field::from_bytes(Bytes::from(vec![b'X'; 9999]));
+        assert_eq!(fs.serialize_field(&codec, &mut buf, &field), Ok(()));
+        assert_eq!(&buf[..4], b"9999");
+
+        let mut buf = BytesMut::new();
+        let field = Field::from_bytes(Bytes::from(vec![b'X'; 10000]));
+        assert_eq!(
+            fs.serialize_field(&codec, &mut buf, &field),
+            Err(RS8583Error::ParseError {
+                error: String::from("TEST: length 10000 above max 9999"),
+            })
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn fs_validate_value() {
+        let mut fs = FieldSpec {
+            field_type: FieldType::N,
+            length_type: LengthType::LLVar,
+            length: 6,
+            ..FieldSpec::default()
+        };
+        assert_eq!(fs.validate_value(3, b"123"), Ok(()));
+        assert_eq!(
+            fs.validate_value(3, b"12A"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 3: invalid character 0x41 for type N"),
+            })
+        );
+        assert_eq!(
+            fs.validate_value(3, b"1234567"),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 3: length 7 above max 6"),
+            })
+        );
+        fs.padding = Padding::Left(b' ');
+        assert_eq!(fs.validate_value(3, b"  123"), Ok(()));
+
+        fs.field_type = FieldType::AN;
+        assert_eq!(fs.validate_value(3, b"AB 12"), Ok(()));
+        assert!(fs.validate_value(3, b"AB-12").is_err());
+        fs.field_type = FieldType::ANS;
+        assert_eq!(fs.validate_value(3, b"AB-12"), Ok(()));
+        assert!(fs.validate_value(3, b"AB\x0012").is_err());
+        fs.field_type = FieldType::B;
+        assert_eq!(fs.validate_value(3, b"AB\x0012"), Ok(()));
+    }
+
+    #[test]
+    fn fs_serialize_max_length() {
+        let codec = Codec::default();
+        let llvar = FieldSpec {
+            name: String::from("LLVAR"),
+            length_type: LengthType::LLVar,
+            length: 999,
+            ..FieldSpec::default()
+        };
+        let lllvar = FieldSpec {
+            name: String::from("LLLVAR"),
+            length_type: LengthType::LLLVar,
+            length: 9999,
+            ..FieldSpec::default()
+        };
+        let fixed = FieldSpec {
+            name: String::from("FIXED"),
+            length: 6,
+            ..FieldSpec::default()
+        };
+        let value = |len| Field::from_bytes(Bytes::from(vec![b'X'; len]));
+
+        let mut buf = BytesMut::new();
+        assert_eq!(llvar.serialize_field(&codec, &mut buf, &value(99)), Ok(()));
+        assert_eq!(buf.len(), 101);
+
+        let cases = [
+            (&llvar, 100, "LLVAR: length 100 above max 99"),
+            (&lllvar, 1000, "LLLVAR: length 1000 above max 999"),
+            (&fixed, 5, "FIXED: length 5 below fixed length 6"),
+            (&fixed, 7, "FIXED: length 7 above fixed length 6"),
+        ];
+        for (fs, len, error) in cases.iter() {
+            let mut buf = BytesMut::new();
+            assert_eq!(
+                fs.serialize_field(&codec, &mut buf, &value(*len)),
+                Err(RS8583Error::ParseError {
+                    error: String::from(*error),
+                })
+            );
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn fs_serialize_trim_padding() {
+        let codec = Codec::default();
+
+        let mut fs = FieldSpec {
+            name: String::from("TEST"),
+            length_type: LengthType::LLVar,
+            length: 20,
+            padding: Padding::Right(b' '),
+            ..FieldSpec::default()
+        };
+        let field = Field::from_bytes(Bytes::from("ABC       "));
+
+        let mut buf = BytesMut::new();
+        fs.serialize_field(&codec, &mut buf, &field).unwrap();
+        assert_eq!(buf.as_ref(), b"10ABC       ");
+
+        fs.trim_padding = true;
+        let mut buf = BytesMut::new();
+        fs.serialize_field(&codec, &mut buf, &field).unwrap();
+        assert_eq!(buf.as_ref(), b"03ABC");
+
+        fs.padding = Padding::Left(b'0');
+        let mut buf = BytesMut::new();
+        let field = Field::from_bytes(Bytes::from("000123"));
+        fs.serialize_field(&codec, &mut buf, &field).unwrap();
+        assert_eq!(buf.as_ref(), b"03123");
+    }
+
+    #[test]
+    fn fs_check_length() {
+        let mut fs = FieldSpec {
+            length: 4,
+            ..FieldSpec::default()
+        };
+        assert_eq!(fs.check_length(2, 4), Ok(()));
+        assert_eq!(
+            fs.check_length(2, 3),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 2: length 3 differs from fixed length 4"),
+            })
+        );
+
+        fs.length_type = LengthType::LLVar;
+        fs.min_length = 2;
+        assert_eq!(fs.check_length(2, 3), Ok(()));
+        assert_eq!(
+            fs.check_length(2, 5),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 2: length 5 above max 4"),
+            })
+        );
+        assert_eq!(
+            fs.check_length(2, 1),
+            Err(RS8583Error::ParseError {
+                error: String::from("field 2: length 1 below min 2"),
+            })
+        );
+    }
+
+    #[test]
+    fn fs_marked_length() {
+        let codec = Codec::default();
+        let fs = FieldSpec {
+            name: String::from("TEST"),
+            field_type: FieldType::B,
+            length_type: LengthType::MarkedVar,
+            length: 1000,
+            ..FieldSpec::default()
+        };
+
+        let mut data = Bytes::from_static(b"\x10\x05HELLO");
+        assert_eq!(fs.to_read(&codec, &mut data), Ok(5));
+        assert_eq!(data.as_ref(), b"HELLO");
+
+        let mut long = b"\x20\x01\x2c".to_vec();
+        long.extend_from_slice(&[0x55; 300]);
+        let mut data = Bytes::from(long.clone());
+        assert_eq!(fs.to_read(&codec, &mut data), Ok(300));
+        assert_eq!(data.len(), 300);
+
+        let mut buf = BytesMut::new();
+        fs.serialize_field(&codec, &mut buf, &Field::from_bytes(Bytes::from("HELLO")))
+            .unwrap();
+        assert_eq!(buf.as_ref(), b"\x10\x05HELLO");
+
+        let mut buf = BytesMut::new();
+        let field = Field::from_bytes(Bytes::from(vec![0x55; 300]));
+        fs.serialize_field(&codec, &mut buf, &field).unwrap();
+        assert_eq!(buf.as_ref(), &long[..]);
+
+        assert_eq!(
+            fs.to_read(&codec, &mut Bytes::from_static(b"\x30\x00\x00\x05")),
+            Err(RS8583Error::ParseError {
+                error: String::from("Invalid length marker: 0x30"),
+            })
+        );
+    }
+
+    #[test]
+    fn fs_length_includes_prefix() {
+        let codec = Codec::default();
+        let fs = FieldSpec {
+            name: String::from("TEST"),
+            length_type: LengthType::LLVar,
+            length: 20,
+            length_includes_prefix: true,
+            ..FieldSpec::default()
+        };
+
+        let mut data = Bytes::from_static(b"07HELLO");
+        assert_eq!(fs.to_read(&codec, &mut data), Ok(5));
+        assert_eq!(data.as_ref(), b"HELLO");
+
+        let mut buf = BytesMut::new();
+        fs.serialize_field(&codec, &mut buf, &Field::from_bytes(Bytes::from("HELLO")))
+            .unwrap();
+        assert_eq!(buf.as_ref(), b"07HELLO");
+
+        assert_eq!(
+            fs.to_read(&codec, &mut Bytes::from_static(b"01")),
+            Err(RS8583Error::ParseError {
+                error: String::from("Length prefix 1 shorter than the prefix itself"),
+            })
+        );
+
+        // The prefix counts towards the largest length it can express
+        let fs = FieldSpec { length: 99, ..fs };
+        assert_eq!(fs.max_value_size(), 97);
+        let mut buf = BytesMut::new();
+        fs.serialize_field(
+            &codec,
+            &mut buf,
+            &Field::from_bytes(Bytes::from(vec![b'X'; 97])),
+        )
+        .unwrap();
+        assert_eq!(&buf[..2], b"99");
+        assert_eq!(
+            fs.serialize_field(
+                &codec,
+                &mut BytesMut::new(),
+                &Field::from_bytes(Bytes::from(vec![b'X'; 98]))
+            ),
+            Err(RS8583Error::ParseError {
+                error: String::from("TEST: length 98 above max 97"),
+            })
+        );
+    }
+
+    #[test]
+    fn fs_bcd_length_prefix() {
+        let codec = Codec {
+            ll_format: VariableLengthFormat::Bcd,
+            ..Codec::default()
+        };
+        let fs = FieldSpec {
+            name: String::from("TEST"),
+            length_type: LengthType::LLLVar,
+            length: 999,
+            ..FieldSpec::default()
+        };
+        let value = Bytes::from(vec![b'X'; 123]);
+
+        let mut data = Bytes::from([&[0x01, 0x23][..], &value[..]].concat());
+        assert_eq!(fs.to_read(&codec, &mut data), Ok(123));
+        assert_eq!(data.len(), 123);
+
+        let mut buf = BytesMut::new();
+        fs.serialize_field(&codec, &mut buf, &Field::from_bytes(value))
+            .unwrap();
+        assert_eq!(&buf[..2], &[0x01, 0x23]);
+        assert_eq!(buf.len(), 125);
+
+        let fs = FieldSpec {
+            length_type: LengthType::LLVar,
+            length: 99,
+            ..fs
+        };
+        let mut data = Bytes::from_static(b"\x19");
+        assert_eq!(fs.to_read(&codec, &mut data), Ok(19));
+        assert_eq!(
+            fs.to_read(&codec, &mut Bytes::from_static(b"\x1a")),
+            Err(RS8583Error::ParseError {
+                error: String::from("Invalid BCD length byte: 0x1a"),
+            })
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn ms_from_yaml() {
+        let spec = MessageSpec::from_yaml_str(
+            r#"
+2: { name: PAN, type: N, length_type: LLVAR, length: 19, sensitivity: MaskPAN }
+3: { name: PROCESSING CODE, type: N, length: 6 }
+48:
+  name: ADDITIONAL DATA
+  length_type: LLLVAR
+  length: 999
+"#,
+        )
+        .unwrap();
+        assert_eq!(spec.fields.len(), 128);
+        let summary: Vec<_> = spec
+            .describe()
+            .into_iter()
+            .map(|s| {
+                (
+                    s.index,
+                    s.name,
+                    s.field_type,
+                    s.length_type,
+                    s.max,
+                    s.sensitivity,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    1,
+                    String::from("PAN"),
+                    FieldType::N,
+                    LengthType::LLVar,
+                    19,
+                    SensitivityType::MaskPAN
+                ),
+                (
+                    2,
+                    String::from("PROCESSING CODE"),
+                    FieldType::N,
+                    LengthType::Fixed,
+                    6,
+                    SensitivityType::Normal
+                ),
+                (
+                    47,
+                    String::from("ADDITIONAL DATA"),
+                    FieldType::ANS,
+                    LengthType::LLLVar,
+                    999,
+                    SensitivityType::Normal
+                ),
+            ]
+        );
+
+        assert!(MessageSpec::from_yaml_str("2: { name: PAN, type: X, length: 19 }").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ms_from_json() {
+        let spec = MessageSpec::from_json_str(
+            r#"{
+                "2": { "name": "PAN", "type": "N", "length_type": "LLVAR", "length": 19 },
+                "4": { "name": "AMOUNT", "type": "N", "length": 12 },
+                "192": { "name": "PRIVATE", "type": "B", "length_type": "LLLVAR", "length": 999 }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(spec.fields.len(), 192);
+        assert_eq!(
+            spec.fields[1].as_ref().unwrap().length_type,
+            LengthType::LLVar
+        );
+        assert!(spec.fields[2].is_none());
+        assert_eq!(spec.fields[3].as_ref().unwrap().length, 12);
+        assert_eq!(spec.fields[191].as_ref().unwrap().field_type, FieldType::B);
+
+        assert_eq!(
+            MessageSpec::from_json_str(r#"{ "0": { "name": "BITMAP", "length": 8 } }"#).err(),
+            Some(RS8583Error::ConfigError {
+                error: String::from("Data element numbers start at 1"),
+            })
+        );
+        for (de, error) in &[
+            ("1", "DE1 is a bitmap continuation bit"),
+            ("65", "DE65 is a bitmap continuation bit"),
+            ("129", "DE129 is a bitmap continuation bit"),
+        ] {
+            let json = format!(r#"{{ "{}": {{ "name": "X", "length": 8 }} }}"#, de);
+            assert_eq!(
+                MessageSpec::from_json_str(&json).err(),
+                Some(RS8583Error::config_error(*error))
+            );
+        }
+
+        // Every number in the file is a DE number
+        let spec = MessageSpec::from_json_str(
+            r#"{
+                "52": { "name": "PIN DATA", "type": "B", "length": 8 },
+                "53": { "name": "SECURITY CONTROL", "type": "N", "length": 16 },
+                "dependencies": [[52, 53]],
+                "sensitivity_policy": { "fields": [[52, "MaskAll"]], "field_types": [] },
+                "presence_policies": {
+                    "0200": { "mandatory": [52], "optional": [53], "conditional": [] }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(spec.dependencies, vec![(51, 52)]);
+        assert_eq!(
+            spec.sensitivity_policy.fields,
+            vec![(51, SensitivityType::MaskAll)]
+        );
+        assert_eq!(spec.presence_policies[b"0200"].mandatory, vec![51]);
+        assert_eq!(spec.presence_policies[b"0200"].optional, vec![52]);
+        let json = serde_json::to_string(&spec).unwrap();
+        assert!(json.contains(r#""dependencies":[[52,53]]"#));
+        assert_eq!(
+            MessageSpec::from_json_str(r#"{ "dependencies": [[0, 53]] }"#).err(),
+            Some(RS8583Error::config_error("Data element numbers start at 1"))
+        );
+
+        // Numbers no message can carry are tolerated and skipped
+        let spec = MessageSpec::from_json_str(
+            r#"{
+                "3": { "name": "PROCESSING CODE", "type": "N", "length": 6 },
+                "200": { "name": "UNKNOWN", "length": 8 },
+                "4294967295": { "name": "UNKNOWN", "length": 8 }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(spec.fields.len(), 128);
+        assert_eq!(
+            spec.describe()
+                .iter()
+                .map(|row| row.index)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ms_serde_round_trip() {
+        let mut spec = MessageSpec {
+            fields: vec![
+                None,
+                Some(FieldSpec {
+                    name: String::from("PAN"),
+                    field_type: FieldType::N,
+                    length_type: LengthType::LLVar,
+                    sensitivity: SensitivityType::MaskPAN,
+                    length: 19,
+                    ..FieldSpec::default()
+                }),
+                Some(FieldSpec {
+                    name: String::from("PROCESSING CODE"),
+                    field_type: FieldType::N,
+                    length: 6,
+                    padding: Padding::Left(b'0'),
+                    ..FieldSpec::default()
+                }),
+            ],
+            ..MessageSpec::default()
+        };
+        spec.add_dependency(2, 1);
+        spec.add_validator(1, |_| Ok(()));
+        spec.set_presence_policy(
+            b"0200",
+            PresencePolicy {
+                mandatory: vec![1, 2],
+                ..PresencePolicy::default()
+            },
+        );
+
+        let json = serde_json::to_string(&spec).unwrap();
+        assert!(json.contains(
+            r#""2":{"name":"PAN","type":"N","length_type":"LLVAR","sensitivity":"MaskPAN""#
+        ));
+        assert!(json.contains(r#""presence_policies":{"0200":"#));
+        assert!(!json.contains("validators"));
+
+        let parsed: MessageSpec = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.describe(), spec.describe());
+        assert_eq!(parsed.dependencies, spec.dependencies);
+        assert_eq!(parsed.presence_policies, spec.presence_policies);
+        assert_eq!(
+            parsed.fields[2].as_ref().unwrap().padding,
+            Padding::Left(b'0')
+        );
+        assert!(parsed.validators.is_empty());
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+
+        let loaded = MessageSpec::from_json_str(&json).unwrap();
+        assert_eq!(loaded.describe(), spec.describe());
+        assert_eq!(loaded.dependencies, spec.dependencies);
+        assert_eq!(loaded.presence_policies, spec.presence_policies);
+    }
+
+    #[cfg(all(feature = "serde", feature = "iso1987"))]
+    #[test]
+    fn ms_serde_iso1987() {
+        let spec = MessageSpec::iso8583_1987();
+        let json = serde_json::to_string(&spec).unwrap();
+        let loaded = MessageSpec::from_json_str(&json).unwrap();
+        let expected: Vec<_> = spec
+            .describe()
+            .into_iter()
+            .filter(|row| row.index % 64 != 0)
+            .collect();
+        assert_eq!(loaded.describe(), expected);
+    }
 }
@@ -0,0 +1,135 @@
+//! Storage of field values within a message.
+//!
+//! The default representation is a slot per field index, which is fastest to
+//! access. With the `sparse` feature, only present fields are stored, in a
+//! vector sorted by index: a typical 20-field message then takes under a
+//! third of the heap memory, at the cost of a binary search per access.
+
+use crate::bitmap::BitMap;
+use crate::field::Field;
+
+#[cfg(not(feature = "sparse"))]
+#[derive(Clone)]
+pub(crate) struct FieldStore {
+    slots: Vec<Option<Field>>,
+}
+
+#[cfg(not(feature = "sparse"))]
+impl FieldStore {
+    pub fn new() -> Self {
+        FieldStore {
+            slots: vec![None; 128],
+        }
+    }
+
+    /// Store with a slot for every field `bitmap` can announce, and at least
+    /// the 128 of primary and secondary bitmaps.
+    pub fn for_bitmap(bitmap: &BitMap) -> Self {
+        FieldStore {
+            slots: vec![None; (bitmap.num_chunks() * 64).max(128)],
+        }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Field> {
+        self.slots.get(idx).and_then(Option::as_ref)
+    }
+
+    pub fn set(&mut self, idx: usize, field: Field) {
+        if idx >= self.slots.len() {
+            self.slots.resize(idx + 1, None);
+        }
+        self.slots[idx] = Some(field);
+    }
+
+    pub fn remove(&mut self, idx: usize) {
+        if let Some(slot) = self.slots.get_mut(idx) {
+            *slot = None;
+        }
+    }
+
+    /// Heap memory held by the store, in bytes.
+    #[cfg(test)]
+    pub fn heap_size(&self) -> usize {
+        self.slots.capacity() * std::mem::size_of::<Option<Field>>()
+    }
+}
+
+#[cfg(feature = "sparse")]
+#[derive(Clone)]
+pub(crate) struct FieldStore {
+    entries: Vec<(usize, Field)>,
+}
+
+#[cfg(feature = "sparse")]
+impl FieldStore {
+    pub fn new() -> Self {
+        FieldStore {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Store with room for every field set in `bitmap`.
+    pub fn for_bitmap(bitmap: &BitMap) -> Self {
+        FieldStore {
+            entries: Vec::with_capacity(bitmap.count_set()),
+        }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Field> {
+        self.entries
+            .binary_search_by_key(&idx, |(i, _)| *i)
+            .ok()
+            .map(|pos| &self.entries[pos].1)
+    }
+
+    pub fn set(&mut self, idx: usize, field: Field) {
+        match self.entries.binary_search_by_key(&idx, |(i, _)| *i) {
+            Ok(pos) => self.entries[pos].1 = field,
+            Err(pos) => self.entries.insert(pos, (idx, field)),
+        }
+    }
+
+    pub fn remove(&mut self, idx: usize) {
+        if let Ok(pos) = self.entries.binary_search_by_key(&idx, |(i, _)| *i) {
+            self.entries.remove(pos);
+        }
+    }
+
+    /// Heap memory held by the store, in bytes.
+    #[cfg(test)]
+    pub fn heap_size(&self) -> usize {
+        self.entries.capacity() * std::mem::size_of::<(usize, Field)>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn store_typical_message() {
+        let mut store = FieldStore::new();
+        for idx in (1..=40).step_by(2) {
+            store.set(idx, Field::from_bytes(Bytes::from(format!("{:06}", idx))));
+        }
+        store.set(3, Field::from_bytes(Bytes::from_static(b"REPLACED")));
+        store.remove(5);
+        store.remove(100);
+
+        assert_eq!(store.get(1).unwrap().as_slice(), b"000001");
+        assert_eq!(store.get(3).unwrap().as_slice(), b"REPLACED");
+        assert!(store.get(2).is_none());
+        assert!(store.get(5).is_none());
+        assert!(store.get(500).is_none());
+
+        // 20 fields of 64 bytes each (two `Bytes` handles), rounded up to
+        // the vector capacity; the dense store always holds 128 slots
+        let field_size = std::mem::size_of::<Option<Field>>();
+        if cfg!(feature = "sparse") {
+            assert!(store.heap_size() <= 32 * (field_size + std::mem::size_of::<usize>()));
+        } else {
+            assert_eq!(store.heap_size(), 128 * field_size);
+        }
+    }
+}
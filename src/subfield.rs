@@ -0,0 +1,229 @@
+//! Subelement layouts carried inside private use fields (DE48 and similar).
+
+use bytes::{Buf, Bytes, BytesMut};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::codec::Codec;
+use crate::error::RS8583Error;
+use crate::field::Field;
+use crate::spec::{FieldSpec, LengthType};
+
+fn subelement_spec() -> FieldSpec {
+    FieldSpec {
+        name: String::from("SUBELEMENT"),
+        length_type: LengthType::LLVar,
+        length: 99,
+        ..FieldSpec::default()
+    }
+}
+
+/// Parse a sequence of subelements, each laid out as a 2-digit ID followed
+/// by an LLVAR value. Digits and lengths follow the codec length settings.
+pub fn parse_subelements(
+    codec: &Codec,
+    data: Bytes,
+) -> Result<BTreeMap<usize, Bytes>, RS8583Error> {
+    let subelement_spec = subelement_spec();
+    let mut cursor = data;
+    let mut subelements = BTreeMap::new();
+
+    while cursor.has_remaining() {
+        if cursor.remaining() < 2 {
+            return Err(RS8583Error::parse_error("Truncated subelement ID"));
+        }
+        let id = codec.digit_value(cursor.get_u8())? * 10 + codec.digit_value(cursor.get_u8())?;
+        let to_read = subelement_spec.to_read(codec, &mut cursor)?;
+        if cursor.remaining() < to_read {
+            return Err(RS8583Error::parse_error(format!(
+                "Subelement {:02} truncated ({} bytes needed, {} available)",
+                id,
+                to_read,
+                cursor.remaining()
+            )));
+        }
+        if subelements.insert(id, cursor.split_to(to_read)).is_some() {
+            return Err(RS8583Error::parse_error(format!(
+                "Duplicate subelement {:02}",
+                id
+            )));
+        }
+    }
+
+    Ok(subelements)
+}
+
+/// Inverse of [`parse_subelements`], writing subelements in ID order.
+pub fn serialize_subelements(
+    codec: &Codec,
+    subelements: &BTreeMap<usize, Bytes>,
+) -> Result<BytesMut, RS8583Error> {
+    let subelement_spec = subelement_spec();
+    let mut buf = BytesMut::new();
+
+    for (id, value) in subelements {
+        codec.serialize_digits(&mut buf, 2, *id)?;
+        let value = Field::from_bytes(value.clone());
+        if value.len() > subelement_spec.max_value_size() {
+            return Err(RS8583Error::parse_error(format!(
+                "Subelement {:02} too long ({} > {})",
+                id,
+                value.len(),
+                subelement_spec.max_value_size()
+            )));
+        }
+        subelement_spec.serialize_field(codec, &mut buf, &value)?;
+    }
+
+    Ok(buf)
+}
+
+/// Positional layout of subfields: each one in turn, fixed length or with
+/// its own length prefix, as described by its `FieldSpec`.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct SubfieldSpec {
+    pub subfields: Vec<FieldSpec>,
+}
+
+impl SubfieldSpec {
+    /// Split `data` into one field per subfield, sharing its buffer. Errors
+    /// carry the index of the subfield within the layout.
+    pub fn parse(&self, codec: &Codec, data: Bytes) -> Result<Vec<Field>, RS8583Error> {
+        let mut cursor = data;
+        let mut fields = Vec::with_capacity(self.subfields.len());
+        for (idx, spec) in self.subfields.iter().enumerate() {
+            let field = spec
+                .parse_value(codec, &mut cursor)
+                .map_err(|e| RS8583Error::field_parse_error(idx, &spec.name, e))?;
+            fields.push(field);
+        }
+        if cursor.has_remaining() {
+            return Err(RS8583Error::parse_error(format!(
+                "{} bytes left after the last subfield",
+                cursor.remaining()
+            )));
+        }
+        Ok(fields)
+    }
+
+    /// Inverse of [`SubfieldSpec::parse`]: concatenate `fields`, one per
+    /// subfield of the layout.
+    pub fn serialize(&self, codec: &Codec, fields: &[Field]) -> Result<BytesMut, RS8583Error> {
+        if fields.len() != self.subfields.len() {
+            return Err(RS8583Error::parse_error(format!(
+                "{} subfields given for a layout of {}",
+                fields.len(),
+                self.subfields.len()
+            )));
+        }
+        let mut buf = BytesMut::new();
+        for (idx, (spec, field)) in self.subfields.iter().zip(fields).enumerate() {
+            spec.serialize_field(codec, &mut buf, field)
+                .map_err(|e| RS8583Error::field_parse_error(idx, &spec.name, e))?;
+        }
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subelements_round_trip() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+
+        let subelements = parse_subelements(&codec, Bytes::from_static(b"0103ABC0205HELLO"))?;
+        assert_eq!(subelements.len(), 2);
+        assert_eq!(subelements[&1].as_ref(), b"ABC");
+        assert_eq!(subelements[&2].as_ref(), b"HELLO");
+        assert_eq!(
+            serialize_subelements(&codec, &subelements)?.as_ref(),
+            b"0103ABC0205HELLO"
+        );
+
+        assert!(parse_subelements(&codec, Bytes::from_static(b""))?.is_empty());
+        assert!(serialize_subelements(&codec, &BTreeMap::new())?.is_empty());
+
+        assert_eq!(
+            parse_subelements(&codec, Bytes::from_static(b"0103ABC0209HELLO")),
+            Err(RS8583Error::ParseError {
+                error: String::from("Subelement 02 truncated (9 bytes needed, 5 available)"),
+            })
+        );
+        assert_eq!(
+            parse_subelements(&codec, Bytes::from_static(b"0103ABC0102XY")),
+            Err(RS8583Error::ParseError {
+                error: String::from("Duplicate subelement 01"),
+            })
+        );
+
+        let mut subelements = BTreeMap::new();
+        subelements.insert(3, Bytes::from(vec![b'X'; 100]));
+        assert_eq!(
+            serialize_subelements(&codec, &subelements),
+            Err(RS8583Error::ParseError {
+                error: String::from("Subelement 03 too long (100 > 99)"),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn subfields_positional() -> Result<(), RS8583Error> {
+        let codec = Codec::default();
+        let layout = SubfieldSpec {
+            subfields: vec![
+                FieldSpec {
+                    name: String::from("ORDER TYPE"),
+                    length: 2,
+                    ..FieldSpec::default()
+                },
+                FieldSpec {
+                    name: String::from("REFERENCE"),
+                    length_type: LengthType::LLVar,
+                    length: 20,
+                    ..FieldSpec::default()
+                },
+            ],
+        };
+        let de48 = Field::from_bytes(Bytes::from_static(b"R209INV-00042"));
+        let subfields = de48.subfields(&codec, &layout)?;
+        assert_eq!(subfields.len(), 2);
+        assert_eq!(subfields[0].as_slice(), b"R2");
+        assert_eq!(subfields[1].as_slice(), b"INV-00042");
+        // Subfields share the field buffer
+        assert_eq!(
+            subfields[1].as_slice().as_ptr(),
+            de48.as_slice()[4..].as_ptr()
+        );
+        assert_eq!(
+            layout.serialize(&codec, &subfields)?.as_ref(),
+            b"R209INV-00042"
+        );
+
+        assert_eq!(
+            layout
+                .parse(&codec, Bytes::from_static(b"R209INV-0004"))
+                .err(),
+            Some(RS8583Error::field_parse_error(
+                1,
+                "REFERENCE",
+                RS8583Error::parse_error("Truncated field"),
+            ))
+        );
+        assert_eq!(
+            layout
+                .parse(&codec, Bytes::from_static(b"R209INV-00042XX"))
+                .err(),
+            Some(RS8583Error::parse_error(
+                "2 bytes left after the last subfield"
+            ))
+        );
+
+        Ok(())
+    }
+}
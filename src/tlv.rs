@@ -0,0 +1,225 @@
+//! BER-TLV data objects, as carried by DE55 (ICC system related data).
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::error::RS8583Error;
+
+/// TLV tag of up to four bytes, stored big-endian: 0x9F26 for the
+/// application cryptogram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tag(pub u32);
+
+impl Tag {
+    /// Encoded bytes of the tag.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let bytes = self.0.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count().min(3);
+        bytes[skip..].to_vec()
+    }
+
+    /// True for constructed tags, whose value is itself a sequence of TLV
+    /// objects.
+    pub fn is_constructed(self) -> bool {
+        self.to_bytes()[0] & 0x20 != 0
+    }
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for b in self.to_bytes() {
+            write!(f, "{:02X}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a sequence of TLV objects. Values of constructed tags are returned
+/// as is; parse them again to walk nested templates.
+pub fn parse_ber_tlv(data: &[u8]) -> Result<Vec<(Tag, Bytes)>, RS8583Error> {
+    parse_tlv_bytes(Bytes::copy_from_slice(data))
+}
+
+/// Like [`parse_ber_tlv`], with values sharing the buffer of `cursor`.
+pub(crate) fn parse_tlv_bytes(mut cursor: Bytes) -> Result<Vec<(Tag, Bytes)>, RS8583Error> {
+    let mut objects = Vec::new();
+    while cursor.has_remaining() {
+        let tag = parse_tag(&mut cursor)?;
+        let len = parse_length(&mut cursor)?;
+        if cursor.remaining() < len {
+            return Err(RS8583Error::parse_error(format!(
+                "TLV value of tag {} truncated ({} bytes declared, {} available)",
+                tag,
+                len,
+                cursor.remaining()
+            )));
+        }
+        objects.push((tag, cursor.split_to(len)));
+    }
+    Ok(objects)
+}
+
+fn parse_tag(cursor: &mut Bytes) -> Result<Tag, RS8583Error> {
+    let truncated = || RS8583Error::parse_error("Truncated TLV tag");
+    if !cursor.has_remaining() {
+        return Err(truncated());
+    }
+    let first = cursor.get_u8();
+    let mut tag = first as u32;
+    // Low five bits all set: the tag number follows in subsequent bytes,
+    // the last of which has its high bit clear
+    if first & 0x1f == 0x1f {
+        loop {
+            if !cursor.has_remaining() {
+                return Err(truncated());
+            }
+            if tag > 0x00ff_ffff {
+                return Err(RS8583Error::parse_error("TLV tag longer than 4 bytes"));
+            }
+            let b = cursor.get_u8();
+            tag = (tag << 8) | b as u32;
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    Ok(Tag(tag))
+}
+
+fn parse_length(cursor: &mut Bytes) -> Result<usize, RS8583Error> {
+    let truncated = || RS8583Error::parse_error("Truncated TLV length");
+    if !cursor.has_remaining() {
+        return Err(truncated());
+    }
+    let first = cursor.get_u8();
+    if first < 0x80 {
+        return Ok(first as usize);
+    }
+    // Long form: 0x81 to 0x84 give the number of length bytes that follow
+    let size = (first & 0x7f) as usize;
+    if size == 0 || size > 4 {
+        return Err(RS8583Error::parse_error(format!(
+            "Unsupported TLV length form 0x{:02x}",
+            first
+        )));
+    }
+    if cursor.remaining() < size {
+        return Err(truncated());
+    }
+    Ok((0..size).fold(0, |len, _| (len << 8) | cursor.get_u8() as usize))
+}
+
+/// Inverse of [`parse_ber_tlv`], writing lengths in their shortest form.
+pub fn serialize_ber_tlv(objects: &[(Tag, Bytes)]) -> Result<BytesMut, RS8583Error> {
+    let mut buf = BytesMut::new();
+    for (tag, value) in objects {
+        buf.extend_from_slice(&tag.to_bytes());
+        let len = value.len();
+        match len {
+            0..=0x7f => buf.put_u8(len as u8),
+            0x80..=0xff => {
+                buf.put_u8(0x81);
+                buf.put_u8(len as u8);
+            }
+            0x100..=0xffff => {
+                buf.put_u8(0x82);
+                buf.put_u16(len as u16);
+            }
+            _ => {
+                return Err(RS8583Error::parse_error(format!(
+                    "TLV value of tag {} too long: {}",
+                    tag, len
+                )))
+            }
+        }
+        buf.extend_from_slice(value);
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Authorization request cryptogram data as sent in DE55
+    const DE55: &[u8] = b"\x9f\x26\x08\x11\x22\x33\x44\x55\x66\x77\x88\
+        \x9f\x27\x01\x80\
+        \x9f\x10\x07\x06\x01\x0a\x03\xa0\x00\x00\
+        \x9f\x37\x04\x12\x34\x56\x78\
+        \x9f\x36\x02\x00\x01\
+        \x95\x05\x00\x00\x00\x00\x00\
+        \x9a\x03\x24\x01\x01\
+        \x9c\x01\x00\
+        \x9f\x02\x06\x00\x00\x00\x00\x10\x00\
+        \x5f\x2a\x02\x08\x40\
+        \x82\x02\x58\x00\
+        \x9f\x1a\x02\x08\x40";
+
+    #[test]
+    fn tlv_de55_round_trip() -> Result<(), RS8583Error> {
+        let objects = parse_ber_tlv(DE55)?;
+        let tags: Vec<_> = objects.iter().map(|(tag, _)| tag.to_string()).collect();
+        assert_eq!(
+            tags,
+            vec![
+                "9F26", "9F27", "9F10", "9F37", "9F36", "95", "9A", "9C", "9F02", "5F2A", "82",
+                "9F1A"
+            ]
+        );
+        assert_eq!(objects[0].1.as_ref(), b"\x11\x22\x33\x44\x55\x66\x77\x88");
+        assert_eq!(
+            objects[8],
+            (Tag(0x9f02), Bytes::from_static(b"\x00\x00\x00\x00\x10\x00"))
+        );
+        assert!(!objects[0].0.is_constructed());
+        assert_eq!(serialize_ber_tlv(&objects)?.as_ref(), DE55);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tlv_nested_and_long_lengths() -> Result<(), RS8583Error> {
+        let inner = vec![
+            (Tag(0x9f27), Bytes::from_static(b"\x80")),
+            (Tag(0x9f36), Bytes::from_static(b"\x00\x01")),
+        ];
+        let template = vec![(Tag(0x77), serialize_ber_tlv(&inner)?.freeze())];
+        let raw = serialize_ber_tlv(&template)?;
+        assert_eq!(
+            raw.as_ref(),
+            b"\x77\x09\x9f\x27\x01\x80\x9f\x36\x02\x00\x01"
+        );
+        let outer = parse_ber_tlv(&raw)?;
+        assert!(outer[0].0.is_constructed());
+        assert_eq!(parse_ber_tlv(&outer[0].1)?, inner);
+
+        let long = vec![
+            (Tag(0x9f10), Bytes::from(vec![0xaa; 200])),
+            (Tag(0x5f20), Bytes::from(vec![b'A'; 300])),
+        ];
+        let raw = serialize_ber_tlv(&long)?;
+        assert_eq!(&raw[..4], b"\x9f\x10\x81\xc8");
+        assert_eq!(&raw[204..209], b"\x5f\x20\x82\x01\x2c");
+        assert_eq!(parse_ber_tlv(&raw)?, long);
+
+        assert_eq!(
+            parse_ber_tlv(b"\x9f\x26\x08\x11\x22"),
+            Err(RS8583Error::parse_error(
+                "TLV value of tag 9F26 truncated (8 bytes declared, 2 available)"
+            ))
+        );
+        assert_eq!(
+            parse_ber_tlv(b"\x9f"),
+            Err(RS8583Error::parse_error("Truncated TLV tag"))
+        );
+        assert_eq!(
+            parse_ber_tlv(b"\x9f\x26\x80"),
+            Err(RS8583Error::parse_error("Unsupported TLV length form 0x80"))
+        );
+        assert_eq!(
+            parse_ber_tlv(b"\x9f\x26\x82\x01"),
+            Err(RS8583Error::parse_error("Truncated TLV length"))
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use bytes::Bytes;
+use rs8583::error::RS8583Error;
+use rs8583::spec::{FieldType, LengthType};
+use rs8583::{Codec, FieldSpec, Message, MessageSpec};
+use rs8583_derive::DataElements;
+
+#[derive(DataElements, Debug, PartialEq)]
+struct Purchase {
+    #[de(2)]
+    pan: String,
+    #[de(4)]
+    amount: u64,
+    #[de(11)]
+    stan: Option<u32>,
+}
+
+fn spec() -> MessageSpec {
+    let mut fields: Vec<Option<FieldSpec>> = (0..128).map(|_| None).collect();
+    fields[1] = Some(FieldSpec {
+        name: String::from("PAN"),
+        field_type: FieldType::N,
+        length_type: LengthType::LLVar,
+        length: 19,
+        ..FieldSpec::default()
+    });
+    fields[3] = Some(FieldSpec {
+        name: String::from("AMOUNT"),
+        field_type: FieldType::N,
+        length: 12,
+        ..FieldSpec::default()
+    });
+    fields[10] = Some(FieldSpec {
+        name: String::from("STAN"),
+        field_type: FieldType::N,
+        length: 6,
+        ..FieldSpec::default()
+    });
+    MessageSpec {
+        fields,
+        ..MessageSpec::default()
+    }
+}
+
+#[test]
+fn derive_round_trip() -> Result<(), RS8583Error> {
+    let codec = Codec::default();
+    let spec = spec();
+    let raw = b"0200\x0a\x04\x00\x00\x00\x00\x00\x00164111111111111111000000001000000042".to_vec();
+    let orig_raw = raw.clone();
+    let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+
+    let purchase = Purchase::from_message(&msg)?;
+    assert_eq!(
+        purchase,
+        Purchase {
+            pan: String::from("4111111111111111"),
+            amount: 1000,
+            stan: Some(42),
+        }
+    );
+
+    let raw = b"0200\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+    let mut reply = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+    purchase.to_message(&mut reply)?;
+    assert_eq!(reply.serialize(&codec)?.as_ref(), &orig_raw[..]);
+
+    let raw = b"0200\x0a\x00\x00\x00\x00\x00\x00\x00164111111111111111000000001000".to_vec();
+    let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw))?;
+    assert_eq!(Purchase::from_message(&msg)?.stan, None);
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use bytes::Bytes;
+use rs8583::spec::{FieldType, LengthType};
+use rs8583::{Codec, FieldSpec, Message, MessageSpec};
+
+fn spec() -> MessageSpec {
+    MessageSpec {
+        fields: vec![
+            None,
+            Some(FieldSpec {
+                name: String::from("PAN"),
+                field_type: FieldType::N,
+                length_type: LengthType::LLVar,
+                length: 19,
+                ..FieldSpec::default()
+            }),
+            Some(FieldSpec {
+                name: String::from("PROCESSING CODE"),
+                field_type: FieldType::N,
+                length_type: LengthType::Fixed,
+                length: 6,
+                ..FieldSpec::default()
+            }),
+            Some(FieldSpec {
+                name: String::from("AMOUNT"),
+                field_type: FieldType::N,
+                length_type: LengthType::Fixed,
+                length: 12,
+                ..FieldSpec::default()
+            }),
+        ],
+        ..MessageSpec::default()
+    }
+}
+
+#[test]
+fn bitmap_introspection() {
+    let codec = Codec::default();
+    let spec = spec();
+    let raw = b"0200\x0a\x00\x00\x00\x00\x00\x00\x00164111111111111111000000001000".to_vec();
+    let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw)).unwrap();
+
+    let bitmap = msg.bitmap();
+    assert!(bitmap.test(1));
+    assert!(!bitmap.test(2));
+    assert!(bitmap.test(3));
+    assert_eq!(bitmap.iter_set().collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(bitmap.count_set(), 2);
+}
+
+#[test]
+fn private_field_passthrough() {
+    let codec = Codec::default();
+    let mut fields: Vec<Option<FieldSpec>> = (0..128).map(|_| None).collect();
+    fields[125] = Some(FieldSpec {
+        name: String::from("PRIVATE USE"),
+        field_type: FieldType::B,
+        length_type: LengthType::LLLVar,
+        length: 999,
+        ..FieldSpec::default()
+    });
+    let spec = MessageSpec {
+        fields,
+        ..MessageSpec::default()
+    };
+    let raw = b"0200\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x20012\x00\xffVENDOR\x01\x02\x03\x04".to_vec();
+    let orig_raw = raw.clone();
+    let msg = Message::from_bytes(&spec, &codec, Bytes::from(raw)).unwrap();
+
+    assert_eq!(msg.bitmap().iter_set().collect::<Vec<_>>(), vec![125]);
+    assert_eq!(
+        msg.field(125).unwrap().as_slice(),
+        b"\x00\xffVENDOR\x01\x02\x03\x04"
+    );
+    assert_eq!(msg.serialize(&codec).unwrap().as_ref(), &orig_raw[..]);
+}